@@ -0,0 +1,270 @@
+//! Sanity checks on a grammar: symbols that can never take part in a
+//! parse, `token_replace` entries that don't replace anything and yacc
+//! actions nothing replaces.
+
+use std::collections::HashMap;
+
+use crate::{
+    config::SingleOrMultiLineString,
+    diagnostic::Diagnostic,
+    grammar::{Grammar, GrammarSymbol, Symbol},
+};
+
+/// The useless symbols of a grammar. A symbol is only listed under the
+/// first reason that applies, in the order of the fields.
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    /// Nonterminals without any rule
+    pub undefined: Vec<Symbol>,
+    /// Nonterminals that can't derive a string of terminals
+    pub unproductive: Vec<Symbol>,
+    /// Nonterminals the start symbol never derives, once the unproductive
+    /// rules are left out
+    pub unreachable: Vec<Symbol>,
+}
+
+impl Analysis {
+    pub fn new(grammar: &Grammar) -> Self {
+        let rules = grammar.get_rules();
+        let n = grammar.iter_symbols().count();
+        let mut defined = vec![false; n];
+        for rule in rules {
+            defined[rule.symbol.index()] = true;
+        }
+
+        let mut productive = vec![false; n];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for rule in rules {
+                if !productive[rule.symbol.index()]
+                    && rule.tokens.iter().all(|s| match s {
+                        GrammarSymbol::Token(_) => true,
+                        GrammarSymbol::Symbol(s) => productive[s.index()],
+                    })
+                {
+                    productive[rule.symbol.index()] = true;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut reachable = vec![false; n];
+        let mut todo = grammar.start().into_iter().collect::<Vec<_>>();
+        while let Some(symbol) = todo.pop() {
+            if std::mem::replace(&mut reachable[symbol.index()], true) {
+                continue;
+            }
+            for rule in rules.iter().filter(|r| r.symbol == symbol) {
+                let symbols = rule.tokens.iter().filter_map(|s| match *s {
+                    GrammarSymbol::Symbol(s) => Some(s),
+                    GrammarSymbol::Token(_) => None,
+                });
+                if symbols.clone().all(|s| productive[s.index()]) {
+                    todo.extend(symbols);
+                }
+            }
+        }
+
+        // The axiom's symbol is useless exactly when the start symbol is
+        let axiom = grammar.axiom().map(|r| rules[r].symbol);
+        let mut analysis = Self::default();
+        for symbol in grammar.iter_symbols().filter(|&s| Some(s) != axiom) {
+            if !defined[symbol.index()] {
+                analysis.undefined.push(symbol);
+            } else if !productive[symbol.index()] {
+                analysis.unproductive.push(symbol);
+            } else if !reachable[symbol.index()] {
+                analysis.unreachable.push(symbol);
+            }
+        }
+        analysis
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.undefined.is_empty() && self.unproductive.is_empty() && self.unreachable.is_empty()
+    }
+
+    /// Whether `symbol` is listed for any reason
+    pub fn is_useless(&self, symbol: Symbol) -> bool {
+        self.undefined.contains(&symbol)
+            || self.unproductive.contains(&symbol)
+            || self.unreachable.contains(&symbol)
+    }
+
+    /// A warning for every useless symbol, pointing at its first rule or,
+    /// without rules, its first use
+    pub fn diagnostics(&self, grammar: &Grammar) -> Vec<Diagnostic> {
+        let rules = grammar.get_rules();
+        let first_rule = |symbol: Symbol| {
+            rules
+                .iter()
+                .find(|r| r.symbol == symbol)
+                .and_then(|r| grammar.locate(r.span))
+        };
+        let first_use = |symbol: Symbol| {
+            rules.iter().find_map(|r| {
+                let i = r
+                    .tokens
+                    .iter()
+                    .position(|&s| s == GrammarSymbol::Symbol(symbol))?;
+                grammar.locate(r.token_spans[i])
+            })
+        };
+        let start = grammar.start().map_or("", |s| grammar.get_symbol(s));
+        let mut diagnostics = Vec::new();
+        for &symbol in &self.undefined {
+            diagnostics.push(
+                Diagnostic::warning(format!(
+                    "non terminal {} has no rules",
+                    grammar.get_symbol(symbol)
+                ))
+                .at(first_use(symbol)),
+            );
+        }
+        for &symbol in &self.unproductive {
+            diagnostics.push(
+                Diagnostic::warning(format!(
+                    "non terminal {} can't derive any string of terminals",
+                    grammar.get_symbol(symbol)
+                ))
+                .at(first_rule(symbol))
+                .note("every one of its rules needs itself, or another such symbol, to finish"),
+            );
+        }
+        for &symbol in &self.unreachable {
+            diagnostics.push(
+                Diagnostic::warning(format!(
+                    "non terminal {} is unreachable from the start symbol {start}",
+                    grammar.get_symbol(symbol)
+                ))
+                .at(first_rule(symbol)),
+            );
+        }
+        diagnostics
+    }
+}
+
+/// A warning for every `token_replace` entry that isn't a token of the
+/// grammar, nor `$`, most likely a token that was renamed or removed
+pub fn unused_replacements(
+    grammar: &Grammar,
+    replacements: &HashMap<String, String>,
+) -> Vec<Diagnostic> {
+    let mut unused = replacements
+        .keys()
+        .filter(|&name| name != "$" && !grammar.iter_tokens().any(|t| grammar.get_token(t) == name))
+        .collect::<Vec<_>>();
+    unused.sort();
+    unused
+        .into_iter()
+        .map(|name| {
+            Diagnostic::warning(format!(
+                "token_replace has an entry for {name}, which isn't a token of the grammar"
+            ))
+        })
+        .collect()
+}
+
+/// A warning for every semantic imported from a yacc action without an
+/// entry in `semantics.replacements`, showing the C code to rewrite
+pub fn unreplaced_actions(
+    grammar: &Grammar,
+    replacements: &HashMap<String, SingleOrMultiLineString>,
+) -> Vec<Diagnostic> {
+    let rules = grammar.get_rules();
+    grammar
+        .iter_semantics()
+        .filter_map(|semantic| {
+            let code = grammar.get_action(semantic)?;
+            let name = grammar.get_semantic(semantic);
+            if replacements.contains_key(name) {
+                return None;
+            }
+            let span = rules.iter().find_map(|r| {
+                let i = r.semantics.iter().position(|&s| s == Some(semantic));
+                i.and_then(|i| r.semantic_spans[i])
+                    .or(r.reduce_sem_span.filter(|_| r.reduce_sem == Some(semantic)))
+            });
+            let code = code.split_whitespace().collect::<Vec<_>>().join(" ");
+            Some(
+                Diagnostic::warning(format!("semantic {name} has no replacement"))
+                    .at(span.and_then(|span| grammar.locate(span)))
+                    .note(format!("it was imported from the yacc action {{ {code} }}")),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    /// `B` and `D` are unproductive, `C` unreachable
+    const USELESS: [&str; 5] = ["S -> a | B | E", "B -> B b", "C -> c", "D -> B d", "E -> e"];
+
+    fn grammar(lines: &[&str]) -> Grammar {
+        Grammar::new(lines.iter().map(|l| Cow::Borrowed(*l))).unwrap()
+    }
+
+    fn names(g: &Grammar, symbols: &[Symbol]) -> Vec<String> {
+        symbols
+            .iter()
+            .map(|&s| g.get_symbol(s).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn useless_symbols() {
+        let g = grammar(&USELESS);
+        let analysis = Analysis::new(&g);
+        assert!(analysis.undefined.is_empty());
+        assert_eq!(names(&g, &analysis.unproductive), ["B", "D"]);
+        assert_eq!(names(&g, &analysis.unreachable), ["C"]);
+        let messages = g
+            .diagnostics()
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].contains("non terminal B can't derive any string of terminals"));
+        assert!(messages[1].contains("non terminal D can't derive any string of terminals"));
+        assert!(messages[2].contains("non terminal C is unreachable from the start symbol S"));
+    }
+
+    #[test]
+    fn prune_renumbers() {
+        let mut g = grammar(&USELESS);
+        g.prune();
+        assert!(Analysis::new(&g).is_empty());
+        let symbols = g
+            .iter_symbols()
+            .map(|s| g.get_symbol(s))
+            .collect::<Vec<_>>();
+        assert_eq!(symbols, ["S", "E", "S'"]);
+        let rules = g.get_rules();
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules[1].tokens, [GrammarSymbol::Symbol(Symbol(1))]);
+        assert_eq!(g.axiom(), Some(3));
+        assert_eq!(g.get_symbol(rules[3].symbol), "S'");
+        assert_eq!(g.start().map(|s| g.get_symbol(s)), Some("S"));
+    }
+
+    #[test]
+    fn unused_token_replacements() {
+        let g = grammar(&["S -> a b"]);
+        let replacements = ["a", "$", "zz", "yy"]
+            .into_iter()
+            .map(|name| (name.to_string(), name.to_uppercase()))
+            .collect();
+        let messages = unused_replacements(&g, &replacements)
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("token_replace has an entry for yy"));
+        assert!(messages[1].contains("token_replace has an entry for zz"));
+    }
+}
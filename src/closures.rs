@@ -1,50 +1,58 @@
 use std::{
-    collections::{HashMap, HashSet},
+    cmp::Ordering,
+    collections::{btree_map::Entry, BTreeMap, HashMap, VecDeque},
     rc::Rc,
 };
 
-use crate::grammar::{Grammar, GrammarSymbol, Rule, Semantic, Symbol, Token};
+use crate::{
+    conflicts::{Conflict, ConflictKind},
+    diagnostic::{Diagnostic, Location, Span},
+    grammar::{Associativity, Grammar, GrammarSymbol, Rule, Semantic, Symbol, Token},
+    lalr,
+    token_set::TokenSet,
+};
 
 #[derive(Debug, Clone)]
 pub struct Item {
     rule: Rule,
     position: usize,
     ruleno: usize,
+    /// LR(1) lookahead tokens, `None` being the end of input. Always empty
+    /// for LR(0) items
+    lookahead: TokenSet,
 }
 
 impl std::hash::Hash for Item {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.position.hash(state);
         self.ruleno.hash(state);
+        self.lookahead.hash(state);
     }
 }
 
 impl PartialEq for Item {
     fn eq(&self, other: &Self) -> bool {
-        self.position == other.position && self.ruleno == other.ruleno
+        self.position == other.position
+            && self.ruleno == other.ruleno
+            && self.lookahead == other.lookahead
     }
 }
 
 impl Eq for Item {}
 
-#[allow(clippy::non_canonical_partial_ord_impl)]
 impl PartialOrd for Item {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.ruleno.partial_cmp(&other.ruleno) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
-        }
-        self.position.partial_cmp(&other.position)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Item {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.ruleno.cmp(&other.ruleno) {
+        match self.core().cmp(&other.core()) {
             core::cmp::Ordering::Equal => {}
             ord => return ord,
         }
-        self.position.cmp(&other.position)
+        self.lookahead.cmp(&other.lookahead)
     }
 }
 
@@ -54,18 +62,37 @@ impl Item {
             rule,
             position: 0,
             ruleno,
+            lookahead: TokenSet::new(),
         }
     }
 
+    pub fn with_lookahead(self, lookahead: TokenSet) -> Self {
+        Self { lookahead, ..self }
+    }
+
+    /// The LR(0) part of the item, `(ruleno, position)`
+    pub const fn core(&self) -> (usize, usize) {
+        (self.ruleno, self.position)
+    }
+
     pub fn current_sem(&self) -> Option<Semantic> {
         self.rule.semantics.get(self.position).copied().flatten()
     }
 
+    pub fn current_sem_span(&self) -> Option<Span> {
+        self.rule
+            .semantic_spans
+            .get(self.position)
+            .copied()
+            .flatten()
+    }
+
     pub fn advance(&self) -> Self {
         Self {
             position: (self.position + 1).min(self.rule.tokens.len() + 1),
             rule: self.rule.clone(),
             ruleno: self.ruleno,
+            lookahead: self.lookahead.clone(),
         }
     }
 
@@ -82,17 +109,7 @@ impl Item {
     }
 
     pub fn print(&self, grammar: &Grammar) {
-        print!("{} -> ", grammar.get_symbol(self.rule.symbol));
-        if self.position == 0 {
-            print!("· ");
-        }
-        for (i, e) in self.rule.tokens.iter().enumerate() {
-            print!("{} ", grammar.get_grammar_symbol(*e));
-            if (i + 1) == self.position {
-                print!("· ");
-            }
-        }
-        println!();
+        println!("{}", self.to_string(grammar));
     }
 
     pub fn to_string(&self, grammar: &Grammar) -> String {
@@ -108,6 +125,15 @@ impl Item {
                 write!(string, "· ").unwrap();
             }
         }
+        if !self.lookahead.is_empty() {
+            let lookahead = self
+                .lookahead
+                .iter()
+                .map(|t| t.map_or("$", |t| grammar.get_token(t)))
+                .collect::<Vec<_>>()
+                .join(" / ");
+            write!(string, ", {lookahead}").unwrap();
+        }
         string
     }
 
@@ -126,33 +152,77 @@ impl Item {
     // }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// A set of items, kept sorted by core. Items with the same core are merged
+/// by joining their lookaheads.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Closure {
-    set: HashSet<Rc<Item>>,
     data: Vec<Rc<Item>>,
 }
 
 impl Closure {
     fn new() -> Self {
-        Self {
-            set: HashSet::new(),
-            data: Vec::new(),
-        }
+        Self { data: Vec::new() }
     }
 
+    /// Adds an item, returning whether the closure changed
     pub fn add(&mut self, item: Item) -> bool {
-        let rc = Rc::new(item);
-        if self.set.insert(rc.clone()) {
-            self.data
-                .insert(self.data.binary_search(&rc).unwrap_err(), rc);
-            true
-        } else {
-            false
+        match self
+            .data
+            .binary_search_by(|other| other.core().cmp(&item.core()))
+        {
+            Err(i) => {
+                self.data.insert(i, Rc::new(item));
+                true
+            }
+            Ok(i) => {
+                if item.lookahead.is_subset(&self.data[i].lookahead) {
+                    false
+                } else {
+                    let mut merged = (*self.data[i]).clone();
+                    merged.lookahead.union_with(&item.lookahead);
+                    self.data[i] = Rc::new(merged);
+                    true
+                }
+            }
+        }
+    }
+
+    /// Adds every item of `other`, returning whether the closure changed
+    pub fn merge(&mut self, other: Self) -> bool {
+        let mut changed = false;
+        for item in other.data {
+            changed |= self.add(Rc::unwrap_or_clone(item));
         }
+        changed
     }
 
-    pub fn len(&self) -> usize {
-        self.data.len()
+    /// The cores of the items, in order
+    pub fn core(&self) -> Vec<(usize, usize)> {
+        self.data.iter().map(|item| item.core()).collect()
+    }
+
+    /// Pager's weak compatibility between two closures with the same core:
+    /// merging them can't create a reduce/reduce conflict that wasn't already
+    /// present in one of them.
+    pub fn weakly_compatible(&self, other: &Self) -> bool {
+        let disjoint = |a: &TokenSet, b: &TokenSet| a.is_disjoint(b);
+        for i in 0..self.data.len() {
+            for j in (i + 1)..self.data.len() {
+                let (a_i, a_j) = (&self.data[i].lookahead, &self.data[j].lookahead);
+                let (b_i, b_j) = (&other.data[i].lookahead, &other.data[j].lookahead);
+                let crossed = !disjoint(a_i, b_j) || !disjoint(b_i, a_j);
+                if crossed && disjoint(a_i, a_j) && disjoint(b_i, b_j) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    pub fn contains(&self, core: (usize, usize)) -> bool {
+        self.data
+            .binary_search_by(|item| item.core().cmp(&core))
+            .is_ok()
     }
 
     pub fn iter(&self) -> Vec<Rc<Item>> {
@@ -169,21 +239,28 @@ impl std::hash::Hash for Closure {
     }
 }
 
+/// Closes a set of items. LR(1) items propagate FIRST(βa) as the lookahead
+/// of the items they add, LR(0) items add LR(0) items.
 pub fn closure(mut items: Closure, grammar: &Grammar) -> Closure {
-    let mut old_items_len = 0;
-    while old_items_len != items.len() {
-        old_items_len = items.len();
+    let mut changed = true;
+    while changed {
+        changed = false;
         for item in items.iter() {
             if let Some(GrammarSymbol::Symbol(sym)) = item.next_gram_sym() {
+                let lookahead = if item.lookahead.is_empty() {
+                    TokenSet::new()
+                } else {
+                    grammar
+                        .first_followed_by(&item.rule.tokens[item.position + 1..], &item.lookahead)
+                };
                 for (i, rule) in grammar
                     .get_rules()
                     .iter()
                     .enumerate()
                     .filter(|(_, x)| x.symbol == sym)
                 {
-                    if !items.add(Item::new(rule.clone(), i)) {
-                        break;
-                    }
+                    changed |=
+                        items.add(Item::new(rule.clone(), i).with_lookahead(lookahead.clone()));
                 }
             }
         }
@@ -191,134 +268,490 @@ pub fn closure(mut items: Closure, grammar: &Grammar) -> Closure {
     items
 }
 
+/// A state and its actions, ordered by token or symbol number, the end of
+/// input coming first
 pub struct AutomataState {
     pub state: usize,
-    pub shift_actions: HashMap<Token, usize>,
-    pub reduce_actions: HashMap<Option<Token>, usize>,
-    pub goto_actions: HashMap<Symbol, usize>,
+    pub shift_actions: BTreeMap<Token, usize>,
+    pub reduce_actions: BTreeMap<Option<Token>, usize>,
+    /// The rules a GLR parser tries on each lookahead. The same as
+    /// `reduce_actions` unless the automaton was built for GLR, which keeps
+    /// every rule of a reduce/reduce conflict, in grammar order, and the
+    /// rules that lost to a shift or to accepting.
+    pub glr_reduce_actions: BTreeMap<Option<Token>, Vec<usize>>,
+    pub goto_actions: BTreeMap<Symbol, usize>,
+    /// Whether the input is accepted if it ends here, in place of reducing
+    /// the axiom
+    pub accept: bool,
 }
 
 impl AutomataState {
     pub fn new(state: usize) -> Self {
         Self {
             state,
-            shift_actions: HashMap::new(),
-            reduce_actions: HashMap::new(),
-            goto_actions: HashMap::new(),
+            shift_actions: BTreeMap::new(),
+            reduce_actions: BTreeMap::new(),
+            glr_reduce_actions: BTreeMap::new(),
+            goto_actions: BTreeMap::new(),
+            accept: false,
+        }
+    }
+}
+
+/// The kind of LR automaton to build, selectable from the config file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutomataKind {
+    /// LR(0) states with reductions on the FOLLOW set of the rule's symbol
+    #[default]
+    Slr,
+    /// LR(0) states with per-item LALR(1) lookaheads (DeRemer–Pennello)
+    Lalr,
+    /// Canonical LR(1) states, where items carry their own lookaheads
+    Lr1,
+    /// LR(1) states merged by core whenever Pager's weak compatibility test
+    /// guarantees no new conflicts: LALR sized tables with LR(1) power
+    Pager,
+}
+
+impl AutomataKind {
+    const fn has_lookaheads(self) -> bool {
+        matches!(self, Self::Lr1 | Self::Pager)
+    }
+}
+
+/// Work list of the state construction. States are referred to by number so
+/// that a merged state can have its closure replaced and be processed again.
+struct Builder {
+    kind: AutomataKind,
+    closures: Vec<Rc<Closure>>,
+    states: Vec<AutomataState>,
+    index: HashMap<Rc<Closure>, usize>,
+    cores: HashMap<Vec<(usize, usize)>, Vec<usize>>,
+    todo: VecDeque<usize>,
+}
+
+impl Builder {
+    /// Finds or creates the state for a closure, queueing it if it is new or
+    /// its lookaheads grew
+    fn state_for(&mut self, c: Closure) -> usize {
+        if let Some(&state) = self.index.get(&c) {
+            return state;
+        }
+        let core = c.core();
+        let compatible = (self.kind == AutomataKind::Pager)
+            .then(|| self.cores.get(&core))
+            .flatten()
+            .and_then(|same_core| {
+                same_core
+                    .iter()
+                    .copied()
+                    .find(|&s| self.closures[s].weakly_compatible(&c))
+            });
+        if let Some(state) = compatible {
+            let mut merged = (*self.closures[state]).clone();
+            if merged.merge(c) {
+                let merged = Rc::new(merged);
+                self.index.remove(&self.closures[state]);
+                self.index.insert(merged.clone(), state);
+                self.closures[state] = merged;
+                if !self.todo.contains(&state) {
+                    self.todo.push_back(state);
+                }
+            }
+            return state;
+        }
+        let state = self.closures.len();
+        let c = Rc::new(c);
+        self.closures.push(c.clone());
+        self.states.push(AutomataState::new(state));
+        self.index.insert(c, state);
+        self.cores.entry(core).or_default().push(state);
+        self.todo.push_back(state);
+        state
+    }
+
+    /// Numbers the states in breadth-first order from the first one,
+    /// following shifts by token then gotos by symbol, so the numbers only
+    /// depend on the grammar. States that merging left without predecessors
+    /// are dropped.
+    fn renumber(&mut self) {
+        let mut renumber = vec![None; self.states.len()];
+        let mut order = vec![0];
+        renumber[0] = Some(0);
+        let mut i = 0;
+        while let Some(&s) = order.get(i) {
+            let state = &self.states[s];
+            for &next in state
+                .shift_actions
+                .values()
+                .chain(state.goto_actions.values())
+            {
+                if renumber[next].is_none() {
+                    renumber[next] = Some(order.len());
+                    order.push(next);
+                }
+            }
+            i += 1;
+        }
+        let mut states = std::mem::take(&mut self.states)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        let closures = std::mem::take(&mut self.closures);
+        for s in order {
+            let mut state = states[s].take().expect("each state is reached once");
+            state.state = renumber[s].unwrap();
+            state
+                .shift_actions
+                .values_mut()
+                .for_each(|s| *s = renumber[*s].unwrap());
+            state
+                .goto_actions
+                .values_mut()
+                .for_each(|s| *s = renumber[*s].unwrap());
+            self.states.push(state);
+            self.closures.push(closures[s].clone());
         }
     }
 }
 
 pub struct Automata {
-    states: HashMap<Rc<Closure>, AutomataState>,
-    state_semantics: HashMap<usize, Semantic>,
-    reduce_semantics: HashMap<usize, Semantic>,
+    /// The states, each at the index of its number
+    states: Vec<(Rc<Closure>, AutomataState)>,
+    state_semantics: BTreeMap<usize, Semantic>,
+    reduce_semantics: BTreeMap<usize, Semantic>,
+    conflicts: Vec<Conflict>,
+    /// Warnings about semantics found while building the states
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl Automata {
-    pub fn new(grammar: &mut Grammar) -> Self {
-        grammar.get_rules().first().cloned().map_or_else(|| Self {
-				states: HashMap::new(),
-                state_semantics: HashMap::new(),
-                reduce_semantics: HashMap::new()
-			}, |axiom| {
-			let mut states = HashMap::new();
-            let mut state_semantics = HashMap::new();
-            let mut reduce_semantics = HashMap::new();
-			let mut todo = Vec::new();
-
-			let i0 = Rc::new(closure({let mut hs = Closure::new(); hs.add(Item::new(axiom, 0)); hs}, grammar));
-			states.insert(i0.clone(), AutomataState::new(0));
-			todo.push(i0);
-			while !todo.is_empty() {
-				let next_state = todo.remove(0);
-				let mut goto_items = HashMap::<_, Vec<_>>::new();
-				let mut shift_items = HashMap::<_, Vec<_>>::new();
-				let mut reduce_items = HashMap::<_, _>::new();
-				for item in next_state.ref_iter() {
-					match item.next_gram_sym() {
-						None => {
-							for x in grammar.follow(item.rule.symbol).as_ref() {
-								if let Some(old ) = reduce_items.insert(*x, item.ruleno) {
-									eprintln!("Reduce - reduce conflict between rule {} and rule {} @ state {}", old, item.ruleno, states.get(&next_state).unwrap().state);
-								}
-							}
-						},
-						Some(GrammarSymbol::Symbol(s)) => {
-							goto_items.entry(s).or_default().push(item.advance())
-						},
-						Some(GrammarSymbol::Token(t)) => {
-							shift_items.entry(t).or_default().push(item.advance())
-						}
-					}
-                    if item.next_gram_sym().is_none() {
-                        if let Some(x) = item.rule.reduce_sem {
-                            if let Some(other) = reduce_semantics.insert(item.ruleno, x) {
-                                if x != other {
-                                    eprintln!("Duplicate error: reduce semantic for rule {} can be more than one thing, either {} or {}", item.ruleno, grammar.get_semantic(x), grammar.get_semantic(other));
-                                }
+    /// Builds the automaton of `kind`. With `glr`, the conflicts that
+    /// precedence doesn't resolve keep all their actions, for a GLR parser
+    /// to try each.
+    pub fn new(grammar: &Grammar, kind: AutomataKind, glr: bool) -> Self {
+        let Some(axiom) = grammar.axiom() else {
+            return Self {
+                states: Vec::new(),
+                state_semantics: BTreeMap::new(),
+                reduce_semantics: BTreeMap::new(),
+                conflicts: Vec::new(),
+                diagnostics: Vec::new(),
+            };
+        };
+        let i0 = closure(
+            {
+                let mut hs = Closure::new();
+                let item = Item::new(grammar.get_rules()[axiom].clone(), axiom);
+                hs.add(if kind.has_lookaheads() {
+                    item.with_lookahead(TokenSet::from_iter([None]))
+                } else {
+                    item
+                });
+                hs
+            },
+            grammar,
+        );
+        let mut builder = Builder {
+            kind,
+            closures: Vec::new(),
+            states: Vec::new(),
+            index: HashMap::new(),
+            cores: HashMap::new(),
+            todo: VecDeque::new(),
+        };
+        builder.state_for(i0);
+        while let Some(state_no) = builder.todo.pop_front() {
+            let next_state = builder.closures[state_no].clone();
+            let mut goto_items = BTreeMap::<_, Vec<_>>::new();
+            let mut shift_items = BTreeMap::<_, Vec<_>>::new();
+            for item in next_state.ref_iter() {
+                match item.next_gram_sym() {
+                    None => (),
+                    Some(GrammarSymbol::Symbol(s)) => {
+                        goto_items.entry(s).or_default().push(item.advance())
+                    }
+                    Some(GrammarSymbol::Token(t)) => {
+                        shift_items.entry(t).or_default().push(item.advance())
+                    }
+                }
+            }
+            builder.states[state_no].goto_actions.clear();
+            builder.states[state_no].shift_actions.clear();
+
+            for (s, items) in goto_items {
+                let mut c = Closure::new();
+                for item in items {
+                    c.add(item);
+                }
+                let goto_state = builder.state_for(closure(c, grammar));
+                builder.states[state_no].goto_actions.insert(s, goto_state);
+            }
+
+            for (t, items) in shift_items {
+                let mut c = Closure::new();
+                for item in items {
+                    c.add(item);
+                }
+                let shift_state = builder.state_for(closure(c, grammar));
+                builder.states[state_no]
+                    .shift_actions
+                    .insert(t, shift_state);
+            }
+        }
+        builder.renumber();
+        let Builder {
+            closures,
+            mut states,
+            ..
+        } = builder;
+
+        let mut state_semantics = BTreeMap::new();
+        let mut state_semantic_spans = HashMap::<_, Option<Location>>::new();
+        let mut reduce_semantics = BTreeMap::new();
+        let mut diagnostics = Vec::new();
+        // (state, item) of every completed item
+        let mut reductions = Vec::new();
+        for (state_no, state) in closures.iter().enumerate() {
+            for item in state.ref_iter() {
+                if item.next_gram_sym().is_none() {
+                    if let Some(x) = item.rule.reduce_sem {
+                        reduce_semantics.insert(item.ruleno, x);
+                    }
+                    reductions.push((state_no, item.clone()));
+                }
+                if let Some(x) = item.current_sem() {
+                    let span = item.current_sem_span().and_then(|s| grammar.locate(s));
+                    if let Some(GrammarSymbol::Symbol(s)) = item.current_gram_sym() {
+                        diagnostics.push(
+                            Diagnostic::warning(format!(
+                                "semantic {} after non terminal symbol {}",
+                                grammar.get_semantic(x),
+                                grammar.get_symbol(s)
+                            ))
+                            .at(span.clone())
+                            .note(format!("it runs on entering state i{state_no}")),
+                        );
+                    }
+                    match state_semantics.entry(state_no) {
+                        Entry::Occupied(other) if *other.get() != x => {
+                            let other_span = &state_semantic_spans[&state_no];
+                            let mut diagnostic = Diagnostic::warning(format!(
+                                "state semantic for state i{state_no} can be either {} or {}",
+                                grammar.get_semantic(x),
+                                grammar.get_semantic(*other.get())
+                            ))
+                            .at(span);
+                            if let Some(other_span) = other_span {
+                                diagnostic = diagnostic.note(format!(
+                                    "{} is at {}:{}:{}",
+                                    grammar.get_semantic(*other.get()),
+                                    other_span.file,
+                                    other_span.line,
+                                    other_span.column
+                                ));
                             }
+                            diagnostics.push(diagnostic);
+                        }
+                        Entry::Occupied(_) => {}
+                        Entry::Vacant(entry) => {
+                            entry.insert(x);
+                            state_semantic_spans.insert(state_no, span);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut lookaheads = match kind {
+            AutomataKind::Slr => {
+                let mut lookaheads = HashMap::new();
+                for (state, item) in &reductions {
+                    lookaheads.insert(
+                        (*state, item.ruleno),
+                        grammar.follow(item.rule.symbol).clone(),
+                    );
+                }
+                lookaheads
+            }
+            AutomataKind::Lalr => lalr::lookaheads(grammar, &states.iter().collect::<Vec<_>>()),
+            AutomataKind::Lr1 | AutomataKind::Pager => reductions
+                .iter()
+                .map(|(state, item)| ((*state, item.ruleno), item.lookahead.clone()))
+                .collect(),
+        };
+
+        // The axiom is only ever complete at the end of the input, where it
+        // accepts instead of reducing
+        let axiom = grammar.axiom();
+        let (accepts, reductions): (Vec<_>, Vec<_>) = reductions
+            .into_iter()
+            .partition(|(_, item)| Some(item.ruleno) == axiom);
+        for (state, _) in &accepts {
+            states[*state].accept = true;
+        }
+
+        // Every reduction proposed on each lookahead of each state, so the
+        // conflicts on it are resolved at once
+        let mut candidates: BTreeMap<(usize, Option<Token>), Vec<Rc<Item>>> = BTreeMap::new();
+        for (state, item) in reductions {
+            for x in lookaheads
+                .remove(&(state, item.ruleno))
+                .unwrap_or_default()
+                .iter()
+            {
+                candidates.entry((state, x)).or_default().push(item.clone());
+            }
+        }
+
+        // Conflicts are resolved like yacc does: by precedence when both the
+        // rule and the token have one, otherwise in favour of the shift or
+        // the rule that comes first in the grammar. A reduction that beats
+        // the shift removes it for all of them, and a %nonassoc tie makes
+        // the lookahead an error whatever else was proposed, while a
+        // %precedence tie isn't resolved. Accepting wins
+        // over reducing, as the shift of the end of input it stands for
+        // would. For GLR only precedence resolves them, like Bison's
+        // %glr-parser.
+        let mut conflicts = Vec::new();
+        for ((state, x), mut items) in candidates {
+            items.sort_by_key(|item| item.ruleno);
+            let state = &mut states[state];
+            // Whether accepting or shifting wins over every reduction
+            // outside of GLR
+            let mut shadowed = false;
+            if x.is_none() && state.accept {
+                let accept_item = accepts
+                    .iter()
+                    .find(|(s, _)| *s == state.state)
+                    .map(|(_, item)| item.clone())
+                    .unwrap();
+                for item in &items {
+                    conflicts.push(Conflict {
+                        state: state.state,
+                        kind: ConflictKind::AcceptReduce,
+                        token: x,
+                        rules: vec![item.ruleno],
+                        items: vec![accept_item.clone(), item.clone()],
+                        kept: glr,
+                    });
+                }
+                if !glr {
+                    continue;
+                }
+                shadowed = true;
+            }
+            if let Some(t) = x.filter(|t| state.shift_actions.contains_key(t)) {
+                // How each reduction compares to the shift, `None` when
+                // they can't be compared
+                let orderings = items
+                    .iter()
+                    .map(|item| {
+                        let (rule, token) = item.rule.precedence.zip(grammar.get_precedence(t))?;
+                        match (rule.level.cmp(&token.level), token.associativity) {
+                            (Ordering::Equal, Associativity::Left) => Some(Ordering::Greater),
+                            (Ordering::Equal, Associativity::Right) => Some(Ordering::Less),
+                            (Ordering::Equal, Associativity::NonAssoc) => Some(Ordering::Equal),
+                            (Ordering::Equal, Associativity::Precedence) => None,
+                            (ordering, _) => Some(ordering),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if orderings.contains(&Some(Ordering::Equal)) {
+                    state.shift_actions.remove(&t);
+                    continue;
+                }
+                let shifts = closures[state.state]
+                    .ref_iter()
+                    .filter(|i| i.next_gram_sym() == Some(GrammarSymbol::Token(t)))
+                    .collect::<Vec<_>>();
+                let winner = items
+                    .iter()
+                    .zip(&orderings)
+                    .find(|(_, ordering)| **ordering == Some(Ordering::Greater))
+                    .map(|(item, _)| item.ruleno);
+                if let Some(winner) = winner {
+                    // The reductions without a precedence still conflicted
+                    // with the shift, they now only compete with `winner`
+                    state.shift_actions.remove(&t);
+                    let mut kept = Vec::new();
+                    for (item, ordering) in items.into_iter().zip(orderings) {
+                        if ordering == Some(Ordering::Less) {
+                            continue;
+                        }
+                        if ordering.is_none() {
+                            let mut items = shifts.clone();
+                            items.push(item.clone());
+                            conflicts.push(Conflict {
+                                state: state.state,
+                                kind: ConflictKind::ShiftReduce,
+                                token: x,
+                                rules: vec![winner, item.ruleno],
+                                items,
+                                kept: false,
+                            });
                         }
+                        kept.push(item);
                     }
-                    // eprintln!("{}: {:?}", item.to_string(grammar), item.current_sem().map(|s| grammar.get_semantic(s)));
-                    if let Some(x) = item.current_sem() {
-                        let state = states.get_mut(&next_state).unwrap().state;
-                        if let Some(GrammarSymbol::Symbol(s)) = item.current_gram_sym() {
-                            eprintln!("Semantic after non terminal symbol: {} @ state i{state} with prev symbol {}", grammar.get_semantic(x), grammar.get_symbol(s));
+                    items = kept;
+                } else {
+                    let mut kept = Vec::new();
+                    for (item, ordering) in items.into_iter().zip(orderings) {
+                        if ordering.is_some() {
+                            continue;
                         }
-                        if let Some(other) = state_semantics.insert(state, x) {
-                            if x != other {
-                                eprintln!("Duplicate error: state semantic for state {} can be more than one thing, either {} or {}", state, grammar.get_semantic(x), grammar.get_semantic(other));
-                            }
+                        shadowed = true;
+                        let mut items = shifts.clone();
+                        items.push(item.clone());
+                        conflicts.push(Conflict {
+                            state: state.state,
+                            kind: ConflictKind::ShiftReduce,
+                            token: x,
+                            rules: vec![item.ruleno],
+                            items,
+                            kept: glr,
+                        });
+                        if glr {
+                            kept.push(item);
                         }
                     }
-				}
-
-				// states.get_mut(&next_state).unwrap().semantic_action = sem_action;
-
-				for (s, items) in goto_items {
-					let mut c = Closure::new();
-					for item in items {
-						c.add(item);
-					}
-					c = closure(c, grammar);
-					let len = states.len();
-					let goto_state = states.entry(Rc::new(c)).or_insert_with_key(|k| {
-						todo.push(k.clone());
-						AutomataState::new(len)
-					}).state;
-					states.get_mut(&next_state).unwrap().goto_actions.insert(s, goto_state);
-				}
-
-				for (t, items) in shift_items {
-					if let Some(rule) = reduce_items.get(&Some(t)) {
-						eprintln!("REDUCE - SHIFT Conflict: Reduce by rule {rule} for {} @ state {}", grammar.get_token(t), states.get(&next_state).unwrap().state)
-					}
-					let mut c = Closure::new();
-					for item in items {
-						c.add(item);
-					}
-					c = closure(c, grammar);
-					let len = states.len();
-					let shift_state = states.entry(Rc::new(c)).or_insert_with_key(|k| {
-						todo.push(k.clone());
-						AutomataState::new(len)
-					}).state;
-					states.get_mut(&next_state).unwrap().shift_actions.insert(t, shift_state);
-				}
-
-				states.get_mut(&next_state).unwrap().reduce_actions = reduce_items;
-
-			}
-
-			Self {states, state_semantics, reduce_semantics}
-		})
+                    items = kept;
+                }
+            }
+            let Some(first) = items.first() else {
+                continue;
+            };
+            for item in &items[1..] {
+                conflicts.push(Conflict {
+                    state: state.state,
+                    kind: ConflictKind::ReduceReduce,
+                    token: x,
+                    rules: vec![first.ruleno, item.ruleno],
+                    items: vec![first.clone(), item.clone()],
+                    kept: glr,
+                });
+            }
+            if !shadowed {
+                state.reduce_actions.insert(x, first.ruleno);
+            }
+            let rules = if glr { &items[..] } else { &items[..1] };
+            state
+                .glr_reduce_actions
+                .insert(x, rules.iter().map(|item| item.ruleno).collect());
+        }
+
+        Self {
+            states: closures.into_iter().zip(states).collect(),
+            state_semantics,
+            reduce_semantics,
+            conflicts,
+            diagnostics,
+        }
     }
 
     pub fn print(&self, grammar: &Grammar) {
-        let mut states = self.states.iter().collect::<Vec<_>>();
-        states.sort_by_key(|(_, state)| state.state);
-        for (closure, state) in states {
+        for (closure, state) in &self.states {
             println!();
             println!();
             println!("############# i{}", state.state);
@@ -338,6 +771,9 @@ impl Automata {
             for (t, rule) in &state.reduce_actions {
                 println!("{} -> Rule {rule}", t.map_or("$", |t| grammar.get_token(t)));
             }
+            if state.accept {
+                println!("ACCEPT ON $");
+            }
         }
         println!();
         println!("STATE SEMANTICS");
@@ -351,18 +787,30 @@ impl Automata {
         }
     }
 
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The states with their closures, in order
     pub fn iter_all(&self) -> impl Iterator<Item = (&Rc<Closure>, &AutomataState)> {
-        self.states.iter()
+        self.states.iter().map(|(closure, state)| (closure, state))
     }
 
+    /// The states, in order
     pub fn iter(&self) -> impl Iterator<Item = &AutomataState> {
-        self.states.values()
+        self.states.iter().map(|(_, state)| state)
     }
 
+    /// The semantics run on entering a state, by state number
     pub fn iter_state_sem(&self) -> impl Iterator<Item = (usize, Semantic)> + '_ {
         self.state_semantics.iter().map(|(&a, &b)| (a, b))
     }
 
+    /// The semantics run on reducing a rule, by rule number
     pub fn iter_reduce_sem(&self) -> impl Iterator<Item = (usize, Semantic)> + '_ {
         self.reduce_semantics.iter().map(|(&a, &b)| (a, b))
     }
@@ -375,3 +823,220 @@ impl Automata {
         self.reduce_semantics.get(&state).copied()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    fn automata(lines: &[&str], kind: AutomataKind) -> (Grammar, Automata) {
+        let grammar = Grammar::new(lines.iter().map(|l| Cow::Borrowed(*l))).unwrap();
+        let automata = Automata::new(&grammar, kind, false);
+        (grammar, automata)
+    }
+
+    fn token(grammar: &Grammar, name: &str) -> Token {
+        grammar
+            .iter_tokens()
+            .find(|&t| grammar.get_token(t) == name)
+            .unwrap()
+    }
+
+    /// `S -> L = R | R`, which is LALR(1) but not SLR(1)
+    const ASSIGNMENT: [&str; 3] = ["S -> L = R | R", "L -> * R | id", "R -> L"];
+
+    /// The classic grammar that is LR(1) but not LALR(1): merging the
+    /// states after `a c` and `b c` makes `A -> c` and `B -> c` conflict
+    const NOT_LALR: [&str; 3] = ["S -> a A d | b B d | a B e | b A e", "A -> c", "B -> c"];
+
+    /// The number of states and conflicts of the automaton of `kind`
+    fn counts(lines: &[&str], kind: AutomataKind) -> (usize, Vec<ConflictKind>) {
+        let (_, automata) = automata(lines, kind);
+        let conflicts = automata.conflicts().iter().map(|c| c.kind).collect();
+        (automata.iter().count(), conflicts)
+    }
+
+    #[test]
+    fn lalr_resolves_what_slr_cannot() {
+        assert_eq!(
+            counts(&ASSIGNMENT, AutomataKind::Slr),
+            (10, vec![ConflictKind::ShiftReduce])
+        );
+        assert_eq!(counts(&ASSIGNMENT, AutomataKind::Lalr), (10, vec![]));
+    }
+
+    #[test]
+    fn lr1_splits_what_lalr_merges() {
+        let reduce_reduce = vec![ConflictKind::ReduceReduce; 2];
+        assert_eq!(counts(&NOT_LALR, AutomataKind::Lalr), (13, reduce_reduce));
+        assert_eq!(counts(&NOT_LALR, AutomataKind::Lr1), (14, vec![]));
+        assert_eq!(counts(&ASSIGNMENT, AutomataKind::Lr1), (14, vec![]));
+    }
+
+    #[test]
+    fn pager_merges_only_compatible_states() {
+        // As small as LALR(1) where merging is harmless, as precise as
+        // LR(1) where it isn't
+        assert_eq!(counts(&ASSIGNMENT, AutomataKind::Pager), (10, vec![]));
+        assert_eq!(counts(&NOT_LALR, AutomataKind::Pager), (14, vec![]));
+    }
+
+    /// The shift, reductions and conflicts on `+` in the state reached by
+    /// shifting `a`, where the rules `A -> a ...` and `B -> a ...` are
+    /// complete
+    fn after_a(precedences: &[&str], a: &str, b: &str) -> (bool, Vec<usize>, Vec<ConflictKind>) {
+        let mut lines = precedences.to_vec();
+        lines.extend(["S -> A '+' | B '+' | a '+' z", a, b]);
+        let (grammar, automata) = automata(&lines, AutomataKind::Lalr);
+        let plus = token(&grammar, "+");
+        let a = automata.iter().next().unwrap().shift_actions[&token(&grammar, "a")];
+        let state = automata.iter().nth(a).unwrap();
+        let conflicts = automata
+            .conflicts()
+            .iter()
+            .filter(|c| c.state == a)
+            .map(|c| c.kind)
+            .collect();
+        (
+            state.shift_actions.contains_key(&plus),
+            state
+                .reduce_actions
+                .get(&Some(plus))
+                .into_iter()
+                .copied()
+                .collect(),
+            conflicts,
+        )
+    }
+
+    #[test]
+    fn a_reduce_beating_the_shift_removes_it() {
+        // `A` can't be compared to the shift but `B` beats it, so `A` is
+        // reported against the shift and then loses to `B`
+        let (shift, reduces, conflicts) =
+            after_a(&["%left '+'", "%left '*'"], "A -> a", "B -> a %prec '*'");
+        assert!(!shift);
+        assert_eq!(reduces, [3]);
+        assert_eq!(
+            conflicts,
+            [ConflictKind::ShiftReduce, ConflictKind::ReduceReduce]
+        );
+    }
+
+    #[test]
+    fn unresolved_reduces_conflict_with_a_kept_shift() {
+        // `A` loses to the shift and `B` can't be compared to it
+        let (shift, reduces, conflicts) = after_a(&["%right '+'"], "A -> a %prec '+'", "B -> a");
+        assert!(shift);
+        assert!(reduces.is_empty());
+        assert_eq!(conflicts, [ConflictKind::ShiftReduce]);
+    }
+
+    #[test]
+    fn a_precedence_tie_is_unresolved() {
+        // `A` ties with the shift on a level without associativity
+        let (shift, reduces, conflicts) =
+            after_a(&["%precedence '+'"], "A -> a %prec '+'", "B -> a");
+        assert!(shift);
+        assert!(reduces.is_empty());
+        assert_eq!(conflicts, [ConflictKind::ShiftReduce; 2]);
+    }
+
+    #[test]
+    fn a_nonassoc_tie_is_an_error() {
+        // `A` beats the shift but `B` ties with it
+        let (shift, reduces, conflicts) = after_a(
+            &["%nonassoc '+'", "%left '*'"],
+            "A -> a %prec '*'",
+            "B -> a %prec '+'",
+        );
+        assert!(!shift);
+        assert!(reduces.is_empty());
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn every_winning_reduce_is_kept() {
+        // Both beat the shift, the first rule wins the reduce/reduce
+        let (shift, reduces, conflicts) = after_a(
+            &["%left '+'", "%left '*'"],
+            "A -> a %prec '+'",
+            "B -> a %prec '*'",
+        );
+        assert!(!shift);
+        assert_eq!(reduces, [3]);
+        assert_eq!(conflicts, [ConflictKind::ReduceReduce]);
+    }
+
+    #[test]
+    fn glr_reduces_are_kept_apart() {
+        let grammar = Grammar::new(["E -> E '+' E | id"].into_iter().map(Cow::Borrowed)).unwrap();
+        let plus = token(&grammar, "+");
+        for glr in [false, true] {
+            let automata = Automata::new(&grammar, AutomataKind::Lalr, glr);
+            // The state of `E -> E '+' E ·`
+            let state = automata
+                .iter()
+                .find(|s| s.shift_actions.contains_key(&plus) && !s.glr_reduce_actions.is_empty())
+                .unwrap();
+            assert!(!state.reduce_actions.contains_key(&Some(plus)));
+            let reduces = state.glr_reduce_actions.get(&Some(plus));
+            assert_eq!(reduces.is_some(), glr);
+            assert!(automata.conflicts().iter().all(|c| c.kept == glr));
+        }
+    }
+
+    /// The transitions of each state, by name
+    fn transitions(grammar: &Grammar, automata: &Automata) -> Vec<Vec<(String, usize)>> {
+        automata
+            .iter()
+            .map(|state| {
+                let shifts = state
+                    .shift_actions
+                    .iter()
+                    .map(|(&t, &s)| (grammar.get_token(t).to_string(), s));
+                let gotos = state
+                    .goto_actions
+                    .iter()
+                    .map(|(&g, &s)| (grammar.get_symbol(g).to_string(), s));
+                shifts.chain(gotos).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn states_are_numbered_breadth_first() {
+        for kind in [
+            AutomataKind::Slr,
+            AutomataKind::Lalr,
+            AutomataKind::Lr1,
+            AutomataKind::Pager,
+        ] {
+            for lines in [&ASSIGNMENT, &NOT_LALR] {
+                let (grammar, built) = automata(lines, kind);
+                let first = transitions(&grammar, &built);
+                // Each state first reached is the next number
+                let mut seen = 1;
+                for (i, (state, next)) in built.iter().zip(&first).enumerate() {
+                    assert_eq!(state.state, i);
+                    for &(_, s) in next {
+                        assert!(s <= seen, "{kind:?}: state {s} reached before state {seen}");
+                        seen = seen.max(s + 1);
+                    }
+                }
+                assert_eq!(seen, first.len());
+                // The same numbers every time
+                let (grammar, again) = automata(lines, kind);
+                assert_eq!(transitions(&grammar, &again), first);
+            }
+        }
+        // Shifts by token, in the order they appear, then gotos by symbol
+        let (grammar, automata) = automata(&ASSIGNMENT, AutomataKind::Lalr);
+        let to = |name: &str, s| (name.to_string(), s);
+        assert_eq!(
+            transitions(&grammar, &automata)[0],
+            [to("*", 1), to("id", 2), to("S", 3), to("L", 4), to("R", 5)]
+        );
+    }
+}
@@ -1,6 +1,14 @@
 use std::{
     collections::{HashMap, HashSet},
-    path::PathBuf,
+    fmt::Display,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    closures::AutomataKind,
+    error::{Error, Result},
 };
 
 #[derive(Debug, serde::Deserialize)]
@@ -11,10 +19,10 @@ pub enum TemplateSource {
 }
 
 impl TemplateSource {
-    pub fn load_string(self) -> String {
+    pub fn load_string(self) -> Result<String> {
         match self {
-            Self::String(s) => s,
-            Self::File { file } => std::fs::read_to_string(file).expect("valid file"),
+            Self::String(s) => Ok(s),
+            Self::File { file } => std::fs::read_to_string(&file).map_err(|e| Error::io(file, e)),
         }
     }
 }
@@ -37,36 +45,126 @@ pub enum PrintOption {
     Shift,
     Reduce,
     Goto,
+    Accept,
+    /// The LL(1) predict table
+    Predict,
+    /// Every action of a state on a token at once, for GLR tables
+    Actions,
     #[serde(rename = "semantics/state")]
     StateSemantics,
     #[serde(rename = "semantics/reduce")]
     ReduceSemantics,
     Dot,
+    /// The grammar as a Bison `.y` file
+    Bison,
+    /// The grammar as an ANTLR4 `.g4` file, named after the result file
+    Antlr,
+    /// The grammar in its own syntax, as rewritten
+    Grammar,
+    /// A recursive descent parser, from the `descent` templates
+    Descent,
+}
+
+/// The templates of a recursive descent parser, rendering one function per
+/// symbol that picks an alternative on the LL(1) predict table
+#[derive(Debug, serde::Deserialize)]
+pub struct DescentConfig {
+    /// A function, its alternatives rendered into `body`
+    pub function: TemplateSource,
+    /// An alternative, taken on any of its `tokens`
+    pub alternative: TemplateSource,
+    /// Matching a token
+    pub terminal: TemplateSource,
+    /// Calling the function of a symbol
+    pub nonterminal: TemplateSource,
+    /// Running a semantic, with its body from the replacements
+    pub semantic: TemplateSource,
+}
+
+/// One grammar file, or several read as a single grammar
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+pub enum GrammarFiles {
+    Single(PathBuf),
+    Multiple(Vec<PathBuf>),
+}
+
+impl GrammarFiles {
+    pub fn paths(&self) -> &[PathBuf] {
+        match self {
+            Self::Single(path) => std::slice::from_ref(path),
+            Self::Multiple(paths) => paths,
+        }
+    }
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct Config {
-    pub grammar: PathBuf,
+    pub grammar: GrammarFiles,
+    #[serde(default)]
+    pub automata: AutomataKind,
+    /// Keep every action of the conflicts precedence doesn't resolve in
+    /// the actions template, for a GLR parser. The other templates still
+    /// get one action per token
+    #[serde(default)]
+    pub glr: bool,
+    /// Drop unreachable and unproductive symbols before building the
+    /// automaton
+    #[serde(default)]
+    pub prune: bool,
+    /// Rewrite left recursive rules, before pruning
+    #[serde(default)]
+    pub remove_left_recursion: bool,
+    /// Factor out the prefixes alternatives share, after removing left
+    /// recursion
+    #[serde(default)]
+    pub left_factor: bool,
     pub reduce_template: TemplateSource,
     pub shift_template: TemplateSource,
     pub goto_template: TemplateSource,
+    /// Needed when a result asks for `accept`
+    #[serde(default)]
+    pub accept_template: Option<TemplateSource>,
+    /// Needed when a result asks for `actions`
+    #[serde(default)]
+    pub actions_template: Option<TemplateSource>,
+    /// Needed when a result asks for `predict`
+    #[serde(default)]
+    pub predict_template: Option<TemplateSource>,
+    /// Needed when a result asks for `descent`
+    #[serde(default)]
+    pub descent: Option<DescentConfig>,
     pub token_replace: HashMap<String, String>,
     pub semantics: SemanticsConfig,
     pub results: HashMap<PathBuf, HashSet<PrintOption>>,
 }
 
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    /// Whether any result asks for `option`
+    pub fn wants(&self, option: PrintOption) -> bool {
+        self.results
+            .values()
+            .any(|options| options.contains(&option))
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 #[serde(untagged)]
 pub enum SingleOrMultiLineString {
     Single(String),
-    Multiline(Vec<String>)
+    Multiline(Vec<String>),
 }
 
-impl ToString for SingleOrMultiLineString {
-    fn to_string(&self) -> String {
+impl Display for SingleOrMultiLineString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Single(s) => s.to_string(),
-            Self::Multiline(v) => v.join("\n"),
+            Self::Single(s) => write!(f, "{s}"),
+            Self::Multiline(v) => write!(f, "{}", v.join("\n")),
         }
     }
 }
@@ -0,0 +1,310 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+};
+
+use crate::{
+    closures::{Automata, Closure, Item},
+    diagnostic::Diagnostic,
+    grammar::{Grammar, GrammarSymbol, Token},
+};
+
+/// The kinds of conflicts, and how they're resolved unless every action is
+/// kept for GLR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Resolved in favour of the shift, unless another rule reduced on the
+    /// token takes precedence over it
+    ShiftReduce,
+    /// Resolved in favour of the rule that comes first in the grammar
+    ReduceReduce,
+    /// The axiom is complete along with another rule at the end of the
+    /// input, resolved in favour of accepting
+    AcceptReduce,
+}
+
+/// A conflict left in the tables after precedence resolution
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub state: usize,
+    pub kind: ConflictKind,
+    /// The lookahead, `None` being the end of input
+    pub token: Option<Token>,
+    /// The rules that could be reduced. For a shift/reduce conflict where
+    /// another rule took precedence over the shift, that rule comes first
+    pub rules: Vec<usize>,
+    /// The items of the state that take part in the conflict
+    pub items: Vec<Rc<Item>>,
+    /// Whether the GLR actions keep every action, instead of resolving the
+    /// conflict
+    pub kept: bool,
+}
+
+/// One way of reaching the conflicting item with the conflicting lookahead
+#[derive(Debug, Clone)]
+pub struct Counterexample {
+    /// The item this derivation ends at
+    pub item: Rc<Item>,
+    /// The sentential form, with a `•` at the conflict point
+    pub example: String,
+    /// The same sentential form with every rule bracketed, `A → [ ... ]`
+    pub derivation: String,
+}
+
+/// A search node: an item in a state, and the lookahead that still has to
+/// appear after it, if any (`Some(None)` being the end of input)
+type Node = (usize, (usize, usize), Option<Option<Token>>);
+
+impl Conflict {
+    /// Finds, for every item in the conflict, the shortest derivation from the
+    /// axiom that reaches the item in the conflict state and is followed by
+    /// the conflicting token. They aren't unifying counterexamples: each
+    /// derivation is the shortest path on its own, so two of them needn't
+    /// share the input before the conflict point, nor after it.
+    pub fn counterexamples(&self, automata: &Automata, grammar: &Grammar) -> Vec<Counterexample> {
+        let closures = automata.iter_all().collect::<Vec<_>>();
+        let mut predecessors = vec![Vec::new(); closures.len()];
+        for (_, state) in &closures {
+            for (&t, &next) in &state.shift_actions {
+                predecessors[next].push((state.state, GrammarSymbol::Token(t)));
+            }
+            for (&s, &next) in &state.goto_actions {
+                predecessors[next].push((state.state, GrammarSymbol::Symbol(s)));
+            }
+        }
+        let closures = closures.into_iter().map(|(c, _)| c).collect::<Vec<_>>();
+        self.items
+            .iter()
+            .filter_map(|item| {
+                let lookahead = if item.next_gram_sym().is_none() {
+                    Some(self.token)
+                } else {
+                    None
+                };
+                let path = search(
+                    grammar,
+                    &closures,
+                    &predecessors,
+                    (self.state, item.core(), lookahead),
+                )?;
+                let (example, derivation) = render(grammar, &path);
+                Some(Counterexample {
+                    item: item.clone(),
+                    example,
+                    derivation,
+                })
+            })
+            .collect()
+    }
+
+    /// A warning pointing at the rule to be reduced, with a counterexample
+    /// for every item in the conflict
+    pub fn diagnostic(&self, automata: &Automata, grammar: &Grammar) -> Diagnostic {
+        let token = self.token.map_or("$", |t| grammar.get_token(t));
+        let message = match self.kind {
+            ConflictKind::ShiftReduce if self.rules.len() == 2 => format!(
+                "shift/reduce conflict on {token} in state i{} with rule {}, which has no precedence, not shifting as rule {} takes precedence",
+                self.state, self.rules[1], self.rules[0]
+            ),
+            ConflictKind::ShiftReduce if self.kept => format!(
+                "shift/reduce conflict on {token} in state i{}, keeping both the shift and reducing rule {}",
+                self.state, self.rules[0]
+            ),
+            ConflictKind::ReduceReduce if self.kept => format!(
+                "reduce/reduce conflict on {token} in state i{} between rule {} and rule {}, keeping both",
+                self.state, self.rules[0], self.rules[1]
+            ),
+            ConflictKind::AcceptReduce if self.kept => format!(
+                "accept/reduce conflict on {token} in state i{}, keeping both accepting and reducing rule {}",
+                self.state, self.rules[0]
+            ),
+            ConflictKind::ShiftReduce => format!(
+                "shift/reduce conflict on {token} in state i{}, shifting instead of reducing rule {}",
+                self.state, self.rules[0]
+            ),
+            ConflictKind::ReduceReduce => format!(
+                "reduce/reduce conflict on {token} in state i{} between rule {} and rule {}, reducing rule {}",
+                self.state, self.rules[0], self.rules[1], self.rules[0]
+            ),
+            ConflictKind::AcceptReduce => format!(
+                "accept/reduce conflict on {token} in state i{}, accepting instead of reducing rule {}",
+                self.state, self.rules[0]
+            ),
+        };
+        let rule = &grammar.get_rules()[*self.rules.last().unwrap()];
+        let mut diagnostic = Diagnostic::warning(message).at(grammar.locate(rule.span));
+        for counterexample in self.counterexamples(automata, grammar) {
+            let action = if Some(counterexample.item.core().0) == grammar.axiom() {
+                "accept"
+            } else if counterexample.item.next_gram_sym().is_none() {
+                "reduce"
+            } else {
+                "shift"
+            };
+            diagnostic = diagnostic
+                .note(format!(
+                    "{action} {}, e.g. {}",
+                    counterexample.item.to_string(grammar).trim_end(),
+                    counterexample.example
+                ))
+                .note(format!("derivation: {}", counterexample.derivation));
+        }
+        diagnostic
+    }
+}
+
+/// Breadth first search backwards from `target` to the axiom item in the
+/// initial state, returning the path from the axiom to the target
+fn search(
+    grammar: &Grammar,
+    closures: &[&Rc<Closure>],
+    predecessors: &[Vec<(usize, GrammarSymbol)>],
+    target: Node,
+) -> Option<Vec<Node>> {
+    let rules = grammar.get_rules();
+    let axiom = grammar.axiom();
+    let mut next = HashMap::<Node, Node>::new();
+    let mut todo = VecDeque::from([target]);
+    while let Some(node @ (state, (ruleno, position), lookahead)) = todo.pop_front() {
+        if state == 0
+            && Some(ruleno) == axiom
+            && position == 0
+            && matches!(lookahead, None | Some(None))
+        {
+            let mut path = vec![node];
+            while let Some(&n) = next.get(path.last().unwrap()) {
+                path.push(n);
+            }
+            return Some(path);
+        }
+        let mut visit = |n: Node| {
+            if n != target && !next.contains_key(&n) {
+                next.insert(n, node);
+                todo.push_back(n);
+            }
+        };
+        if position > 0 {
+            let symbol = rules[ruleno].tokens[position - 1];
+            for &(p, s) in &predecessors[state] {
+                if s == symbol && closures[p].contains((ruleno, position - 1)) {
+                    visit((p, (ruleno, position - 1), lookahead));
+                }
+            }
+        } else {
+            let symbol = GrammarSymbol::Symbol(rules[ruleno].symbol);
+            for parent in closures[state].ref_iter() {
+                if parent.next_gram_sym() != Some(symbol) {
+                    continue;
+                }
+                let (parent_rule, parent_position) = parent.core();
+                let rest = &rules[parent_rule].tokens[parent_position + 1..];
+                let lookahead = match lookahead {
+                    None => None,
+                    Some(t) => {
+                        if t.is_some() && grammar.first(rest).contains(t) {
+                            None
+                        } else if grammar.derives_empty(rest) {
+                            Some(t)
+                        } else {
+                            continue;
+                        }
+                    }
+                };
+                visit((state, parent.core(), lookahead));
+            }
+        }
+    }
+    None
+}
+
+fn render(grammar: &Grammar, path: &[Node]) -> (String, String) {
+    let rules = grammar.get_rules();
+    let mut example = Vec::new();
+    let (axiom, _) = path[0].1;
+    let mut derivation = vec![format!("{} → [", grammar.get_symbol(rules[axiom].symbol))];
+    let mut parents = Vec::new();
+    for pair in path.windows(2) {
+        let ((ruleno, position), (next_rule, next_position)) = (pair[0].1, pair[1].1);
+        if (next_rule, next_position) == (ruleno, position + 1) {
+            let symbol = grammar.get_grammar_symbol(rules[ruleno].tokens[position]);
+            example.push(symbol);
+            derivation.push(symbol.to_string());
+        } else {
+            derivation.push(format!(
+                "{} → [",
+                grammar.get_symbol(rules[next_rule].symbol)
+            ));
+            parents.push((ruleno, position + 1));
+        }
+    }
+    example.push("•");
+    derivation.push("•".to_string());
+    let (ruleno, position) = path.last().unwrap().1;
+    for (ruleno, position) in std::iter::once((ruleno, position)).chain(parents.into_iter().rev()) {
+        for &symbol in &rules[ruleno].tokens[position..] {
+            let symbol = grammar.get_grammar_symbol(symbol);
+            example.push(symbol);
+            derivation.push(symbol.to_string());
+        }
+        derivation.push("]".to_string());
+    }
+    (example.join(" "), derivation.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::closures::AutomataKind;
+
+    /// The example and derivation of every item of every conflict
+    fn counterexamples(lines: &[&str]) -> Vec<Vec<(String, String)>> {
+        let grammar = Grammar::new(lines.iter().map(|l| Cow::Borrowed(*l))).unwrap();
+        let automata = Automata::new(&grammar, AutomataKind::Lalr, false);
+        automata
+            .conflicts()
+            .iter()
+            .map(|c| {
+                c.counterexamples(&automata, &grammar)
+                    .into_iter()
+                    .map(|c| (c.example, c.derivation))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn pair(example: &str, derivation: &str) -> (String, String) {
+        (example.to_string(), derivation.to_string())
+    }
+
+    #[test]
+    fn ambiguous_operator() {
+        assert_eq!(
+            counterexamples(&["E -> E '+' E | id"]),
+            [[
+                pair("E + E • + E", "E' → [ E → [ E + E → [ E • + E ] ] ]"),
+                pair("E + E • + E", "E' → [ E → [ E → [ E + E • ] + E ] ]"),
+            ]]
+        );
+    }
+
+    #[test]
+    fn dangling_else() {
+        // Each derivation is the shortest on its own, so the shift needs
+        // one `if` and the reduce two
+        assert_eq!(
+            counterexamples(&["S -> if c then S | if c then S else S | s"]),
+            [[
+                pair(
+                    "if c then S • else S",
+                    "S' → [ S → [ if c then S • else S ] ]"
+                ),
+                pair(
+                    "if c then if c then S • else S",
+                    "S' → [ S → [ if c then S → [ if c then S • ] else S ] ]"
+                ),
+            ]]
+        );
+    }
+}
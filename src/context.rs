@@ -25,6 +25,90 @@ pub struct GotoContext<'a> {
     pub next: usize,
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct AcceptContext<'a> {
+    pub state: usize,
+    /// The end of input, `$` after `token_replace`
+    pub token: &'a str,
+}
+
+/// One of the actions of a state on a token, with the fields of its kind
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ActionContext<'a> {
+    pub shift: bool,
+    pub reduce: bool,
+    pub accept: bool,
+    /// The state shifted to
+    pub next: Option<usize>,
+    /// The rule reduced
+    pub ruleno: Option<usize>,
+    pub elements: Option<usize>,
+    pub symbol_no: Option<Symbol>,
+    pub symbol_not_replaced: Option<&'a str>,
+}
+
+/// Every action of a state on a token: shifts, then accepting, then
+/// reduces. There's more than one only where the automaton was built for
+/// GLR and kept a conflict.
+#[derive(Debug, serde::Serialize)]
+pub struct ActionsContext<'a> {
+    pub state: usize,
+    pub token: &'a str,
+    pub actions: Vec<ActionContext<'a>>,
+}
+
+/// An entry of the LL(1) predict table
+#[derive(Debug, serde::Serialize)]
+pub struct PredictContext<'a> {
+    pub symbol_no: Symbol,
+    pub symbol_not_replaced: &'a str,
+    pub token: &'a str,
+    pub ruleno: usize,
+    pub elements: usize,
+    /// The names of the rule's symbols and tokens, before `token_replace`
+    pub rhs: Vec<&'a str>,
+}
+
+/// A function of the recursive descent parser, parsing one symbol
+#[derive(Debug, serde::Serialize)]
+pub struct DescentFunctionContext<'a> {
+    pub symbol_no: Symbol,
+    pub symbol_not_replaced: &'a str,
+    /// The name made an identifier, `E_` for `E'`
+    pub symbol_ident: &'a str,
+    /// The tokens some alternative is taken on, after `token_replace`
+    pub expected: Vec<String>,
+    /// The alternatives, one after the other
+    pub body: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DescentAlternativeContext<'a> {
+    pub symbol_no: Symbol,
+    pub symbol_not_replaced: &'a str,
+    pub symbol_ident: &'a str,
+    pub ruleno: usize,
+    /// Which alternative of the function it is, from 0
+    pub index: usize,
+    /// The lookaheads it's taken on, after `token_replace`
+    pub tokens: Vec<String>,
+    /// Its terminals, nonterminals and semantics, one after the other
+    pub body: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DescentTerminalContext<'a> {
+    pub token: &'a str,
+    pub token_not_replaced: &'a str,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DescentNonterminalContext<'a> {
+    pub symbol_no: Symbol,
+    pub symbol_not_replaced: &'a str,
+    pub symbol_ident: &'a str,
+}
+
 #[derive(Debug, serde::Serialize)]
 pub struct SemStateCaseContext {
     pub state: usize,
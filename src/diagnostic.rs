@@ -0,0 +1,148 @@
+use std::fmt::Display;
+
+/// A place in a grammar source file. Lines and columns start at 1, columns
+/// and lengths count characters. The default span, on line 0, belongs to
+/// generated rules that weren't written anywhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub file: usize,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `end`, which must be
+    /// on the same line or the span stops at the end of the first line
+    pub fn to(self, end: Span) -> Span {
+        if end.file != self.file || end.line != self.line {
+            return self;
+        }
+        Span {
+            len: (end.column + end.len).saturating_sub(self.column).max(1),
+            ..self
+        }
+    }
+}
+
+/// A grammar source file, kept around to point diagnostics into
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub lines: Vec<String>,
+}
+
+impl SourceFile {
+    /// The span of `len` bytes starting at byte `start` of line `line`
+    /// (counted from 0)
+    pub fn span(&self, file: usize, line: usize, start: usize, len: usize) -> Span {
+        let text = &self.lines[line];
+        Span {
+            file,
+            line: line + 1,
+            column: text[..start].chars().count() + 1,
+            len: text[start..start + len].chars().count().max(1),
+        }
+    }
+
+    /// Resolves a span into this file for a diagnostic
+    pub fn location(&self, span: Span) -> Option<Location> {
+        Some(Location {
+            file: self.name.clone(),
+            line: span.line,
+            column: span.column,
+            len: span.len,
+            text: self.lines.get(span.line.checked_sub(1)?)?.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A span resolved against its source file, so the diagnostic can be shown
+/// without the grammar around
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub location: Option<Location>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            location: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message)
+        }
+    }
+
+    pub fn at(self, location: impl Into<Option<Location>>) -> Self {
+        Self {
+            location: location.into(),
+            ..self
+        }
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+/// Shown as `file:line:col` with the offending line and a caret underline
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        writeln!(f, "{severity}: {}", self.message)?;
+        let mut gutter = 1;
+        if let Some(location) = &self.location {
+            let line = location.line.to_string();
+            gutter = line.len();
+            writeln!(
+                f,
+                "{:gutter$}--> {}:{}:{}",
+                "", location.file, location.line, location.column
+            )?;
+            writeln!(f, "{:gutter$} |", "")?;
+            writeln!(f, "{line} | {}", location.text)?;
+            writeln!(
+                f,
+                "{:gutter$} | {:pad$}{}",
+                "",
+                "",
+                "^".repeat(location.len),
+                pad = location.column - 1
+            )?;
+        }
+        for note in &self.notes {
+            writeln!(f, "{:gutter$} = note: {note}", "")?;
+        }
+        Ok(())
+    }
+}
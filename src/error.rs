@@ -0,0 +1,88 @@
+use std::{fmt::Display, io, path::PathBuf};
+
+use crate::diagnostic::Diagnostic;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A file couldn't be read or written
+    Io { path: PathBuf, source: io::Error },
+    /// The config file isn't valid json or doesn't match [`crate::Config`]
+    Config(serde_json::Error),
+    /// The grammar file isn't valid
+    Grammar(Box<Diagnostic>),
+    /// A template couldn't be parsed or rendered
+    Template {
+        name: String,
+        source: tinytemplate::error::Error,
+    },
+    /// A result file asks for something whose template isn't configured
+    MissingTemplate {
+        path: PathBuf,
+        /// What the result asks for, as written in `results`
+        kind: &'static str,
+        /// The config key that would hold the template
+        template: &'static str,
+    },
+}
+
+impl Error {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub fn template(name: impl Into<String>, source: tinytemplate::error::Error) -> Self {
+        Self::Template {
+            name: name.into(),
+            source,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Config(e) => write!(f, "invalid config file: {e}"),
+            Self::Grammar(e) => write!(f, "{}", e.to_string().trim_end()),
+            Self::Template { name, source } => write!(f, "template {name}: {source}"),
+            Self::MissingTemplate {
+                path,
+                kind,
+                template,
+            } => write!(
+                f,
+                "{} asks for {kind} but the config has no {template}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Config(e) => Some(e),
+            Self::Grammar(_) => None,
+            Self::Template { source, .. } => Some(source),
+            Self::MissingTemplate { .. } => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Config(e)
+    }
+}
+
+impl From<Diagnostic> for Error {
+    fn from(e: Diagnostic) -> Self {
+        Self::Grammar(Box::new(e))
+    }
+}
@@ -0,0 +1,444 @@
+//! Writes a grammar for other parser generators, to compare their conflict
+//! reports with [`Automata`](crate::Automata)'s, or back in its own syntax
+//! once it's been rewritten.
+//!
+//! Symbols are renamed into identifiers each tool accepts, so `list(Item)`
+//! becomes `list_Item_`, and the axiom is left out since both tools add
+//! their own. Semantics only appear as comments, as actions in the middle
+//! of a rule would add states of their own.
+
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+    diagnostic::SourceFile,
+    grammar::{Associativity, Grammar, GrammarSymbol, Rule, Token},
+    lexer::{self, LexemeKind},
+};
+
+/// Turns `names` into distinct identifiers, replacing every run of other
+/// characters with `_`, starting with a letter and applying `fix`, which
+/// may change the case or avoid keywords
+pub(crate) fn identifiers<'a>(
+    names: impl Iterator<Item = &'a str>,
+    taken: &mut HashSet<String>,
+    fix: impl Fn(String) -> String,
+) -> Vec<String> {
+    names
+        .map(|name| {
+            let mut ident = String::new();
+            for c in name.chars() {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    ident.push(c);
+                } else if !ident.ends_with('_') {
+                    ident.push('_');
+                }
+            }
+            if !ident.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                ident.insert(0, 'x');
+            }
+            let ident = fix(ident);
+            let mut unique = ident.clone();
+            let mut n = 1;
+            while !taken.insert(unique.clone()) {
+                unique = format!("{ident}{n}");
+                n += 1;
+            }
+            unique
+        })
+        .collect()
+}
+
+/// Whether a token is written as a name rather than a literal
+fn is_named(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `name` lexes back as a single word
+fn is_word(name: &str) -> bool {
+    let source = SourceFile {
+        name: String::new(),
+        lines: vec![name.to_string()],
+    };
+    matches!(
+        lexer::lex(&source, 0).as_deref(),
+        Ok([lexeme]) if lexeme.kind == LexemeKind::Word(name.to_string())
+    )
+}
+
+/// `value` between `quote`s, with C style escapes
+fn literal(value: &str, quote: char) -> String {
+    let mut literal = String::from(quote);
+    for c in value.chars() {
+        match c {
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            c if c == quote => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push(quote);
+    literal
+}
+
+/// The token giving `rule` its precedence when it isn't the last token
+/// with one, which has to be written with `%prec`
+fn prec_token(grammar: &Grammar, rule: &Rule) -> Option<Token> {
+    let implicit = rule.tokens.iter().rev().find_map(|s| match *s {
+        GrammarSymbol::Token(t) => grammar.get_precedence(t),
+        GrammarSymbol::Symbol(_) => None,
+    });
+    let precedence = rule.precedence?;
+    if implicit == Some(precedence) {
+        return None;
+    }
+    grammar
+        .iter_tokens()
+        .find(|&t| grammar.get_precedence(t) == Some(precedence))
+}
+
+/// The tokens with a precedence, grouped by level from the loosest
+fn precedence_levels(grammar: &Grammar) -> Vec<(Associativity, Vec<Token>)> {
+    let mut levels = Vec::<(usize, Associativity, Vec<Token>)>::new();
+    for token in grammar.iter_tokens() {
+        let Some(precedence) = grammar.get_precedence(token) else {
+            continue;
+        };
+        match levels.iter_mut().find(|(l, ..)| *l == precedence.level) {
+            Some((.., tokens)) => tokens.push(token),
+            None => levels.push((precedence.level, precedence.associativity, vec![token])),
+        }
+    }
+    levels.sort_by_key(|&(level, ..)| level);
+    levels
+        .into_iter()
+        .map(|(_, associativity, tokens)| (associativity, tokens))
+        .collect()
+}
+
+/// The body of `rule` with `symbol` writing each symbol, and its semantics
+/// as comments
+fn body(grammar: &Grammar, rule: &Rule, symbol: impl Fn(GrammarSymbol) -> String) -> String {
+    let mut body = Vec::new();
+    for (i, &s) in rule.tokens.iter().enumerate() {
+        if let Some(sem) = rule.semantics.get(i).copied().flatten() {
+            body.push(format!("/* {{{}}} */", grammar.get_semantic(sem)));
+        }
+        body.push(symbol(s));
+    }
+    if let Some(sem) = rule.semantics.get(rule.tokens.len()).copied().flatten() {
+        body.push(format!("/* {{{}}} */", grammar.get_semantic(sem)));
+    }
+    body.join(" ")
+}
+
+/// The rules to export, grouped by symbol with the start symbol first
+fn grouped(grammar: &Grammar) -> Vec<Vec<&Rule>> {
+    let rules = grammar.get_rules();
+    let axiom = grammar.axiom().map(|r| rules[r].symbol);
+    let mut symbols = grammar
+        .iter_symbols()
+        .filter(|&s| Some(s) != axiom)
+        .collect::<Vec<_>>();
+    symbols.sort_by_key(|&s| Some(s) != grammar.start());
+    symbols
+        .into_iter()
+        .map(|s| rules.iter().filter(|r| r.symbol == s).collect::<Vec<_>>())
+        .filter(|rules| !rules.is_empty())
+        .collect()
+}
+
+impl Grammar {
+    /// The grammar as a Bison `.y` file, without a prologue, epilogue or
+    /// actions. Tokens that are neither identifiers nor a single character
+    /// are declared with a name and used through their string alias.
+    pub fn to_bison(&self) -> String {
+        let mut taken = HashSet::new();
+        let tokens = self.iter_tokens().map(|t| self.get_token(t));
+        let token_names = identifiers(tokens, &mut taken, |ident| ident);
+        let symbols = self.iter_symbols().map(|s| self.get_symbol(s));
+        let symbol_names = identifiers(symbols, &mut taken, |ident| ident);
+
+        let token = |t: Token| {
+            let name = self.get_token(t);
+            if is_named(name) {
+                token_names[t.index()].clone()
+            } else if name.chars().count() == 1 {
+                literal(name, '\'')
+            } else {
+                literal(name, '"')
+            }
+        };
+        let mut out = String::new();
+        for t in self.iter_tokens() {
+            let name = self.get_token(t);
+            if is_named(name) {
+                writeln!(out, "%token {}", token_names[t.index()]).unwrap();
+            } else if name.chars().count() > 1 {
+                let alias = literal(name, '"');
+                writeln!(out, "%token {} {alias}", token_names[t.index()]).unwrap();
+            }
+        }
+        for (associativity, tokens) in precedence_levels(self) {
+            let directive = match associativity {
+                Associativity::Left => "%left",
+                Associativity::Right => "%right",
+                Associativity::NonAssoc => "%nonassoc",
+                Associativity::Precedence => "%precedence",
+            };
+            let tokens = tokens.into_iter().map(token).collect::<Vec<_>>();
+            writeln!(out, "{directive} {}", tokens.join(" ")).unwrap();
+        }
+        if let Some(start) = self.start() {
+            writeln!(out, "%start {}", symbol_names[start.index()]).unwrap();
+        }
+        writeln!(out, "%%").unwrap();
+
+        let symbol = |s: GrammarSymbol| match s {
+            GrammarSymbol::Token(t) => token(t),
+            GrammarSymbol::Symbol(s) => symbol_names[s.index()].clone(),
+        };
+        for rules in grouped(self) {
+            writeln!(out).unwrap();
+            writeln!(out, "{}", symbol_names[rules[0].symbol.index()]).unwrap();
+            for (i, rule) in rules.into_iter().enumerate() {
+                let mut line = body(self, rule, symbol);
+                if rule.tokens.is_empty() {
+                    line.insert_str(0, "%empty ");
+                }
+                if let Some(t) = prec_token(self, rule) {
+                    write!(line, " %prec {}", token(t)).unwrap();
+                }
+                if let Some(sem) = rule.reduce_sem {
+                    write!(line, " {{ /* R{{{}}} */ }}", self.get_semantic(sem)).unwrap();
+                }
+                let lead = if i == 0 { ':' } else { '|' };
+                writeln!(out, "    {}", format!("{lead} {line}").trim_end()).unwrap();
+            }
+            writeln!(out, "    ;").unwrap();
+        }
+        out
+    }
+
+    /// The grammar in its own syntax, one rule per line so they keep their
+    /// numbers, and the axiom left out as it's added back. Every token is
+    /// declared first so they keep theirs too. Symbols whose names don't
+    /// read back as a single word, like `list(Item)`, are renamed.
+    pub fn to_source(&self) -> String {
+        let axiom = self.axiom().map(|r| self.get_rules()[r].symbol);
+        let symbols = self
+            .iter_symbols()
+            .filter(|&s| Some(s) != axiom)
+            .map(|s| self.get_symbol(s))
+            .collect::<Vec<_>>();
+        let mut taken = symbols
+            .iter()
+            .copied()
+            .chain(self.iter_tokens().map(|t| self.get_token(t)))
+            .filter(|name| is_word(name))
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+        let mut symbol_names = Vec::new();
+        for name in self.iter_symbols().map(|s| self.get_symbol(s)) {
+            if is_word(name) {
+                symbol_names.push(name.to_string());
+            } else {
+                let mut renamed = identifiers(std::iter::once(name), &mut taken, |ident| ident);
+                symbol_names.append(&mut renamed);
+            }
+        }
+
+        let token = |t: Token| {
+            let name = self.get_token(t);
+            if is_word(name) && !symbols.contains(&name) {
+                name.to_string()
+            } else {
+                lexer::quote(name)
+            }
+        };
+        let mut out = String::new();
+        let tokens = self.iter_tokens().map(token).collect::<Vec<_>>();
+        if !tokens.is_empty() {
+            writeln!(out, "%token {}", tokens.join(" ")).unwrap();
+        }
+        for (associativity, tokens) in precedence_levels(self) {
+            let directive = match associativity {
+                Associativity::Left => "%left",
+                Associativity::Right => "%right",
+                Associativity::NonAssoc => "%nonassoc",
+                Associativity::Precedence => "%precedence",
+            };
+            let tokens = tokens.into_iter().map(token).collect::<Vec<_>>();
+            writeln!(out, "{directive} {}", tokens.join(" ")).unwrap();
+        }
+        if let Some(start) = self.start() {
+            writeln!(out, "%start {}", symbol_names[start.index()]).unwrap();
+        }
+        writeln!(out).unwrap();
+
+        let symbol = |s: GrammarSymbol| match s {
+            GrammarSymbol::Token(t) => token(t),
+            GrammarSymbol::Symbol(s) => symbol_names[s.index()].clone(),
+        };
+        for (i, rule) in self.get_rules().iter().enumerate() {
+            if Some(i) == self.axiom() {
+                continue;
+            }
+            let mut line = format!("{} -> ", symbol_names[rule.symbol.index()]);
+            for (i, &s) in rule.tokens.iter().enumerate() {
+                if let Some(sem) = rule.semantics.get(i).copied().flatten() {
+                    write!(line, "{{{}}} ", self.get_semantic(sem)).unwrap();
+                }
+                write!(line, "{} ", symbol(s)).unwrap();
+            }
+            if let Some(sem) = rule.semantics.get(rule.tokens.len()).copied().flatten() {
+                write!(line, "{{{}}} ", self.get_semantic(sem)).unwrap();
+            }
+            if let Some(t) = prec_token(self, rule) {
+                write!(line, "%prec {} ", token(t)).unwrap();
+            }
+            if let Some(sem) = rule.reduce_sem {
+                write!(line, "R{{{}}}", self.get_semantic(sem)).unwrap();
+            }
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
+        out
+    }
+
+    /// The grammar as an ANTLR4 `.g4` file named `name`. Named tokens are
+    /// declared in a `tokens` block, without lexer rules. ANTLR has no
+    /// precedence declarations, so they and `%prec` are only comments,
+    /// and it only takes direct left recursion.
+    pub fn to_antlr(&self, name: &str) -> String {
+        const KEYWORDS: [&str; 14] = [
+            "catch", "channels", "finally", "fragment", "grammar", "import", "lexer", "locals",
+            "mode", "options", "parser", "returns", "throws", "tokens",
+        ];
+        let mut taken = HashSet::new();
+        let tokens = self.iter_tokens().map(|t| self.get_token(t));
+        let token_names = identifiers(tokens, &mut taken, |mut ident| {
+            ident[..1].make_ascii_uppercase();
+            ident
+        });
+        let symbols = self.iter_symbols().map(|s| self.get_symbol(s));
+        let symbol_names = identifiers(symbols, &mut taken, |mut ident| {
+            ident[..1].make_ascii_lowercase();
+            if KEYWORDS.contains(&ident.as_str()) {
+                ident.push('_');
+            }
+            ident
+        });
+
+        let token = |t: Token| {
+            let name = self.get_token(t);
+            if is_named(name) {
+                token_names[t.index()].clone()
+            } else {
+                literal(name, '\'')
+            }
+        };
+        let mut out = String::new();
+        writeln!(out, "grammar {name};").unwrap();
+        let named = self
+            .iter_tokens()
+            .filter(|&t| is_named(self.get_token(t)))
+            .map(|t| token_names[t.index()].as_str())
+            .collect::<Vec<_>>();
+        if !named.is_empty() {
+            writeln!(out).unwrap();
+            writeln!(out, "tokens {{ {} }}", named.join(", ")).unwrap();
+        }
+        let levels = precedence_levels(self);
+        if !levels.is_empty() {
+            writeln!(out).unwrap();
+        }
+        for (associativity, tokens) in levels {
+            let directive = match associativity {
+                Associativity::Left => "%left",
+                Associativity::Right => "%right",
+                Associativity::NonAssoc => "%nonassoc",
+                Associativity::Precedence => "%precedence",
+            };
+            let tokens = tokens.into_iter().map(token).collect::<Vec<_>>();
+            writeln!(out, "// {directive} {}", tokens.join(" ")).unwrap();
+        }
+
+        let symbol = |s: GrammarSymbol| match s {
+            GrammarSymbol::Token(t) => token(t),
+            GrammarSymbol::Symbol(s) => symbol_names[s.index()].clone(),
+        };
+        for rules in grouped(self) {
+            writeln!(out).unwrap();
+            writeln!(out, "{}", symbol_names[rules[0].symbol.index()]).unwrap();
+            for (i, rule) in rules.into_iter().enumerate() {
+                let mut line = body(self, rule, symbol);
+                if let Some(t) = prec_token(self, rule) {
+                    write!(line, " /* %prec {} */", token(t)).unwrap();
+                }
+                if let Some(sem) = rule.reduce_sem {
+                    write!(line, " /* R{{{}}} */", self.get_semantic(sem)).unwrap();
+                }
+                let lead = if i == 0 { ':' } else { '|' };
+                writeln!(out, "    {}", format!("{lead} {line}").trim_end()).unwrap();
+            }
+            writeln!(out, "    ;").unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[test]
+    fn antlr() {
+        let grammar = Grammar::new(
+            [
+                "%left '+'",
+                "%right '^'",
+                "Expr -> Expr '+' Expr | Expr '^' Expr | Fragment | id",
+                "Fragment -> '(' Expr ')' | tokens",
+                "tokens -> num",
+            ]
+            .into_iter()
+            .map(Cow::Borrowed),
+        )
+        .unwrap();
+        // Names are capitalised for tokens and lowercased for rules, with
+        // ANTLR keywords escaped
+        assert_eq!(
+            grammar.to_antlr("Calc"),
+            "grammar Calc;
+
+tokens { Id, Num }
+
+// %left '+'
+// %right '^'
+
+expr
+    : expr '+' expr
+    | expr '^' expr
+    | fragment_
+    | Id
+    ;
+
+fragment_
+    : '(' expr ')'
+    | tokens_
+    ;
+
+tokens_
+    : Num
+    ;
+"
+        );
+    }
+}
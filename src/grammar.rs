@@ -1,14 +1,43 @@
 use std::{
     borrow::Cow,
-    collections::{HashMap, HashSet},
-    process::exit,
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+use crate::{
+    analysis::Analysis,
+    diagnostic::{Diagnostic, Location, SourceFile, Span},
+    error::{Error, Result},
+    lexer::{self, Lexeme, LexemeKind},
+    parser,
+    token_set::TokenSet,
+    yacc,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize)]
 #[serde(transparent)]
-pub struct Symbol(usize);
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Token(usize);
+pub struct Symbol(pub(crate) usize);
+
+impl Symbol {
+    pub const fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Token(pub(crate) usize);
+
+impl Token {
+    pub const fn index(self) -> usize {
+        self.0
+    }
+
+    pub const fn from_index(index: usize) -> Self {
+        Self(index)
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum GrammarSymbol {
@@ -16,9 +45,26 @@ pub enum GrammarSymbol {
     Symbol(Symbol),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize)]
 #[serde(transparent)]
-pub struct Semantic(usize);
+pub struct Semantic(pub(crate) usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Associativity {
+    Left,
+    Right,
+    NonAssoc,
+    /// Only a level, from `%precedence`, so a tie stays a conflict
+    Precedence,
+}
+
+/// Precedence of a token or rule, declared with `%left`, `%right`,
+/// `%nonassoc` or `%precedence`. Later declarations bind tighter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Precedence {
+    pub level: usize,
+    pub associativity: Associativity,
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Rule {
@@ -26,6 +72,31 @@ pub struct Rule {
     pub tokens: Vec<GrammarSymbol>,
     pub semantics: Vec<Option<Semantic>>,
     pub reduce_sem: Option<Semantic>,
+    /// From `%prec`, or else the last token in the rule with a precedence
+    pub precedence: Option<Precedence>,
+    pub span: Span,
+    /// Where each of `tokens` was written
+    pub token_spans: Vec<Span>,
+    /// Where each of `semantics` was written
+    pub semantic_spans: Vec<Option<Span>>,
+    pub reduce_sem_span: Option<Span>,
+}
+
+impl Rule {
+    /// A rule with an empty body
+    pub fn new(symbol: Symbol, span: Span) -> Self {
+        Self {
+            symbol,
+            tokens: Vec::new(),
+            semantics: Vec::new(),
+            reduce_sem: None,
+            precedence: None,
+            span,
+            token_spans: Vec::new(),
+            semantic_spans: Vec::new(),
+            reduce_sem_span: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -34,128 +105,217 @@ pub struct Grammar {
     symbols: Vec<String>,
     tokens: Vec<String>,
     semantics: Vec<String>,
-    firsts: HashMap<Vec<GrammarSymbol>, HashSet<Option<Token>>>,
-    follows: HashMap<Symbol, HashSet<Option<Token>>>,
+    precedences: HashMap<Token, Precedence>,
+    start: Option<Symbol>,
+    /// The rule `S' -> S` added after the others
+    axiom: Option<usize>,
+    firsts: Vec<TokenSet>,
+    nullable: Vec<bool>,
+    follows: Vec<TokenSet>,
+    sources: Vec<SourceFile>,
+    /// The C code of the semantics imported from yacc actions
+    actions: HashMap<Semantic, String>,
+    /// Warnings found while reading the grammar
+    diagnostics: Vec<Diagnostic>,
 }
 
-fn add_or_get<T, U: Copy, F: FnOnce(U) -> T, C: Fn(&T, U) -> bool>(
-    arr: &mut Vec<T>,
-    data: U,
-    cond: C,
-    map: F,
-) -> usize {
-    arr.iter()
-        .enumerate()
-        .find_map(|(i, s)| if cond(s, data) { Some(i) } else { None })
-        .unwrap_or_else(|| {
-            arr.push(map(data));
-            arr.len() - 1
-        })
+/// Grows every set along `edges` (from a set to the sets that include it)
+/// until nothing changes
+fn propagate(sets: &mut [TokenSet], edges: &[Vec<usize>]) {
+    let mut queued = vec![true; sets.len()];
+    let mut worklist = (0..sets.len()).collect::<VecDeque<_>>();
+    while let Some(from) = worklist.pop_front() {
+        queued[from] = false;
+        for &to in &edges[from] {
+            if to != from {
+                let set = sets[from].clone();
+                if sets[to].union_with(&set) && !queued[to] {
+                    queued[to] = true;
+                    worklist.push_back(to);
+                }
+            }
+        }
+    }
 }
 
-impl Grammar {
-    pub fn new<'a, I: Iterator<Item = Cow<'a, str>>>(lines: I) -> Self {
-        let mut symbols = Vec::new();
-        let mut tokens = Vec::new();
-        let mut semantics = Vec::new();
-        let mut rules = Vec::new();
-        let lines = lines.filter(|s| !s.is_empty()).collect::<Vec<_>>();
-        let mut rules_unparsed = Vec::with_capacity(lines.len());
-        for line in lines {
-            let Some((a, b)) = line.split_once("->") else {
-                println!("Error in line {line}, ignoring");
+/// Reads grammar files and the files they `%include`, each one once, with
+/// the statements of an included file taking the place of its `%include`
+#[derive(Default)]
+struct Loader {
+    sources: Vec<SourceFile>,
+    statements: Vec<Vec<Lexeme>>,
+    /// The C code of the actions of yacc files, by semantic name
+    actions: HashMap<String, String>,
+    loaded: HashSet<PathBuf>,
+}
+
+impl Loader {
+    /// Reads `path`, unless it's already been read. `included_at` is the
+    /// `%include` naming it, if any, to point at when it can't be read.
+    /// Files ending in `.y` are read as yacc grammars.
+    fn load(&mut self, path: &Path, included_at: Option<Span>) -> Result<()> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if !self.loaded.insert(canonical) {
+            return Ok(());
+        }
+        let lines = File::open(path).and_then(|file| {
+            BufReader::new(file)
+                .lines()
+                .collect::<std::io::Result<Vec<_>>>()
+        });
+        let lines = match (lines, included_at) {
+            (Ok(lines), _) => lines,
+            (Err(e), Some(span)) => {
+                let location = self.sources.get(span.file).and_then(|s| s.location(span));
+                return Err(
+                    Diagnostic::error(format!("can't read {}: {e}", path.display()))
+                        .at(location)
+                        .into(),
+                );
+            }
+            (Err(e), None) => return Err(Error::io(path, e)),
+        };
+        let source = SourceFile {
+            name: path.display().to_string(),
+            lines,
+        };
+        if path.extension().is_some_and(|e| e == "y") {
+            let file = self.sources.len();
+            let import = yacc::import(&source, file)?;
+            self.sources.push(source);
+            self.statements.extend(import.statements);
+            self.actions.extend(import.actions);
+            return Ok(());
+        }
+        self.add(source, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Lexes `source`, with its includes resolved relative to `dir`
+    fn add(&mut self, source: SourceFile, dir: &Path) -> Result<()> {
+        let file = self.sources.len();
+        let lexemes = lexer::lex(&source, file)?;
+        self.sources.push(source);
+        for statement in parser::statements(&lexemes) {
+            let [Lexeme {
+                kind: LexemeKind::Directive(directive),
+                span,
+            }, rest @ ..] = statement
+            else {
+                self.statements.push(statement.to_vec());
                 continue;
             };
-            let symbol = a.trim();
-            let symbol = Symbol(add_or_get(
-                &mut symbols,
-                symbol,
-                |s, a| s == a,
-                ToString::to_string,
-            ));
-            rules_unparsed.push((symbol, b.to_string()));
-        }
-        for (symbol, b) in rules_unparsed {
-            let mut toks = Vec::new();
-            let mut reduce_sem = None;
-            let mut sems = Vec::new();
-            let mut last_sem = false;
-            for tok_or_sem in b.split_whitespace() {
-                if tok_or_sem.starts_with('{') && tok_or_sem.ends_with('}') {
-                    let sem = &tok_or_sem[1..tok_or_sem.len() - 1];
-                    let sem = Some(Semantic(add_or_get(
-                        &mut semantics,
-                        sem,
-                        |s, a| s == a,
-                        ToString::to_string,
-                    )));
-                    if last_sem {
-                        println!("Error, duplicate semantics");
-                        exit(1);
-                    }
-                    sems.push(sem);
-                    last_sem = true;
-                } else if tok_or_sem.starts_with("R{") && tok_or_sem.ends_with('}') {
-                    let sem = &tok_or_sem[2..tok_or_sem.len() - 1];
-                    let sem = Some(Semantic(add_or_get(
-                        &mut semantics,
-                        sem,
-                        |s, a| s == a,
-                        ToString::to_string,
-                    )));
-                    reduce_sem = sem;
-                } else {
-                    let tok = symbols
-                        .iter()
-                        .enumerate()
-                        .find(|(_, s)| *s == tok_or_sem)
-                        .map_or_else(
-                            || {
-                                GrammarSymbol::Token(Token(add_or_get(
-                                    &mut tokens,
-                                    tok_or_sem,
-                                    |t, a| t == a,
-                                    ToString::to_string,
-                                )))
-                            },
-                            |(i, _)| GrammarSymbol::Symbol(Symbol(i)),
-                        );
-                    toks.push(tok);
-                    if !last_sem {
-                        sems.push(None);
-                    }
-                    last_sem = false;
+            if directive != "include" {
+                self.statements.push(statement.to_vec());
+                continue;
+            }
+            match rest {
+                [Lexeme {
+                    kind: LexemeKind::Quoted(path),
+                    span,
+                }] => self.load(&dir.join(path), Some(*span))?,
+                _ => {
+                    let location = self.sources[file].location(*span);
+                    return Err(
+                        Diagnostic::error("expected a quoted file name after %include")
+                            .at(location)
+                            .into(),
+                    );
                 }
             }
+        }
+        Ok(())
+    }
+}
 
-            let rule = Rule {
-                symbol,
-                tokens: toks,
-                semantics: sems,
-                reduce_sem,
-            };
-            rules.push(rule);
+impl Grammar {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::from_files(&[path])
+    }
+
+    /// Reads several grammar files as one grammar, in order, as if they
+    /// were a single file ending a statement at the end of each. Files
+    /// ending in `.y` are imported from yacc.
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut loader = Loader::default();
+        for path in paths {
+            loader.load(path.as_ref(), None)?;
         }
-        let mut hs = HashSet::with_capacity(1);
-        hs.insert(None);
-        let mut follows = HashMap::with_capacity(1);
-        follows.insert(rules[0].symbol, hs);
+        Self::parse(loader)
+    }
+
+    pub fn new<'a, I: Iterator<Item = Cow<'a, str>>>(lines: I) -> Result<Self> {
+        let mut loader = Loader::default();
+        let source = SourceFile {
+            name: "<grammar>".to_string(),
+            lines: lines.map(Cow::into_owned).collect(),
+        };
+        loader.add(source, Path::new(""))?;
+        Self::parse(loader)
+    }
+
+    fn parse(loader: Loader) -> Result<Self> {
+        let parsed = parser::parse(&loader.sources, &loader.statements)?;
+        let actions = parsed
+            .semantics
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| Some((Semantic(i), loader.actions.get(name)?.clone())))
+            .collect();
         let mut s = Self {
-            symbols,
-            tokens,
-            rules,
-            semantics,
-            firsts: HashMap::new(),
-            follows,
+            symbols: parsed.symbols,
+            tokens: parsed.tokens,
+            rules: parsed.rules,
+            semantics: parsed.semantics,
+            precedences: parsed.precedences,
+            start: parsed.start,
+            axiom: None,
+            firsts: Vec::new(),
+            nullable: Vec::new(),
+            follows: Vec::new(),
+            sources: loader.sources,
+            actions,
+            diagnostics: parsed.diagnostics,
         };
-        s.follows();
-        s
+        s.augment();
+        s.compute_sets();
+        let analysis = Analysis::new(&s);
+        s.diagnostics.extend(analysis.diagnostics(&s));
+        Ok(s)
+    }
+
+    /// Resolves a span of this grammar's sources for a diagnostic
+    pub fn locate(&self, span: Span) -> Option<Location> {
+        self.sources.get(span.file)?.location(span)
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
     }
 
     pub fn get_rules(&self) -> &[Rule] {
         &self.rules
     }
 
+    /// The symbol every input derives from, `None` without rules
+    pub fn start(&self) -> Option<Symbol> {
+        self.start
+    }
+
+    /// The rule the automaton starts from, `S' -> S` for the start symbol
+    /// `S`. It's never reduced, the automaton accepts instead once it's
+    /// complete at the end of the input.
+    pub fn axiom(&self) -> Option<usize> {
+        self.axiom
+    }
+
+    pub fn iter_symbols(&self) -> impl Iterator<Item = Symbol> {
+        (0..self.symbols.len()).map(Symbol)
+    }
+
+    pub fn iter_tokens(&self) -> impl Iterator<Item = Token> {
+        (0..self.tokens.len()).map(Token)
+    }
+
     pub fn get_symbol(&self, symbol: Symbol) -> &str {
         self.symbols.get(symbol.0).map(AsRef::as_ref).unwrap()
     }
@@ -167,6 +327,19 @@ impl Grammar {
         self.semantics.get(semantic.0).map(AsRef::as_ref).unwrap()
     }
 
+    /// The C code of a semantic imported from a yacc action
+    pub fn get_action(&self, semantic: Semantic) -> Option<&str> {
+        self.actions.get(&semantic).map(AsRef::as_ref)
+    }
+
+    pub fn iter_semantics(&self) -> impl Iterator<Item = Semantic> {
+        (0..self.semantics.len()).map(Semantic)
+    }
+
+    pub fn get_precedence(&self, token: Token) -> Option<Precedence> {
+        self.precedences.get(&token).copied()
+    }
+
     pub fn get_grammar_symbol(&self, s: GrammarSymbol) -> &str {
         match s {
             GrammarSymbol::Token(t) => self.get_token(t),
@@ -174,162 +347,241 @@ impl Grammar {
         }
     }
 
-    fn first(&mut self, v: &[GrammarSymbol]) -> Cow<'_, HashSet<Option<Token>>> {
-        if self.firsts.contains_key(v) {
-            return Cow::Borrowed(self.firsts.get(v).unwrap());
+    /// Drops the symbols [`Analysis`] finds useless along with every rule
+    /// using them. The symbols and rules left are renumbered, in the same
+    /// order, while tokens keep their numbers.
+    pub fn prune(&mut self) {
+        let analysis = Analysis::new(self);
+        if analysis.is_empty() {
+            return;
         }
-        let mut f = HashSet::new();
-        f.insert(None);
-        let mut iter = v.iter();
-        while f.contains(&None) {
-            match iter.next() {
-                None => break,
-                Some(GrammarSymbol::Token(tok)) => {
-                    f.remove(&None);
-                    f.insert(Some(*tok));
-                }
-                Some(GrammarSymbol::Symbol(sym)) => {
-                    f.remove(&None);
-                    #[allow(clippy::needless_collect)]
-                    for rule in self
-                        .rules
-                        .iter()
-                        .filter(|r| r.symbol == *sym)
-                        .map(|rule| rule.tokens.to_vec())
-                        .collect::<Vec<_>>()
-                    {
-                        f.extend(self.first(&rule).as_ref());
-                    }
-                }
+        let mut renumbered = vec![None; self.symbols.len()];
+        let mut symbols = Vec::new();
+        for (i, name) in std::mem::take(&mut self.symbols).into_iter().enumerate() {
+            if !analysis.is_useless(Symbol(i)) {
+                renumbered[i] = Some(Symbol(symbols.len()));
+                symbols.push(name);
             }
         }
-        self.firsts.insert(v.to_vec(), f.clone());
-        Cow::Owned(f)
-    }
-
-    fn first_non_mut(&self, v: &[GrammarSymbol]) -> HashSet<Option<Token>> {
-        let mut f = HashSet::new();
-        f.insert(None);
-        let mut iter = v.iter();
-        while f.contains(&None) {
-            match iter.next() {
-                None => break,
-                Some(GrammarSymbol::Token(tok)) => {
-                    f.remove(&None);
-                    f.insert(Some(*tok));
-                }
-                Some(GrammarSymbol::Symbol(sym)) => {
-                    f.remove(&None);
-                    #[allow(clippy::needless_collect)]
-                    for rule in self
-                        .rules
-                        .iter()
-                        .filter(|r| r.symbol == *sym)
-                        .map(|rule| rule.tokens.to_vec())
-                        .collect::<Vec<_>>()
-                    {
-                        f.extend(self.first_non_mut(&rule));
-                    }
-                }
+        self.symbols = symbols;
+        let mut axiom = None;
+        for (i, mut rule) in std::mem::take(&mut self.rules).into_iter().enumerate() {
+            let tokens = rule
+                .tokens
+                .iter()
+                .map(|&s| match s {
+                    GrammarSymbol::Token(_) => Some(s),
+                    GrammarSymbol::Symbol(s) => renumbered[s.0].map(GrammarSymbol::Symbol),
+                })
+                .collect::<Option<Vec<_>>>();
+            let (Some(symbol), Some(tokens)) = (renumbered[rule.symbol.0], tokens) else {
+                continue;
+            };
+            if Some(i) == self.axiom {
+                axiom = Some(self.rules.len());
             }
+            rule.symbol = symbol;
+            rule.tokens = tokens;
+            self.rules.push(rule);
         }
-        f
+        self.axiom = axiom;
+        self.start = self.start.and_then(|s| renumbered[s.0]);
+        self.compute_sets();
     }
 
-    // fn print_set(&self, set: &HashSet<Option<Token>>) {
-    //     print!("{{");
-    //     for x in set {
-    //         print!("{}, ", x.map_or("None", |x| self.get_token(x)))
-    //     }
-    //     print!("}}");
-    // }
+    /// Adds the axiom `S' -> S` as the last rule, so the rules that were
+    /// written keep their numbers
+    fn augment(&mut self) {
+        let Some(start) = self.start else {
+            return;
+        };
+        let mut rule = Rule::new(self.add_symbol_like(start), Span::default());
+        rule.tokens.push(GrammarSymbol::Symbol(start));
+        rule.token_spans.push(Span::default());
+        rule.semantics.push(None);
+        rule.semantic_spans.push(None);
+        self.rules.push(rule);
+        self.axiom = Some(self.rules.len() - 1);
+    }
 
-    pub fn follow(&mut self, v: Symbol) -> Cow<'_, HashSet<Option<Token>>> {
-        if self.follows.contains_key(&v) {
-            return Cow::Borrowed(self.follows.get(&v).unwrap());
+    /// Adds a symbol named after `like` with a prime, `E'` for `E`, or as
+    /// many as it takes to be new
+    pub(crate) fn add_symbol_like(&mut self, like: Symbol) -> Symbol {
+        let mut name = format!("{}'", self.symbols[like.0]);
+        while self.symbols.contains(&name) {
+            name.push('\'');
         }
-        unreachable!()
-        // println!();
-        // print!("Follow {} ", self.get_symbol(v));
-        // self.follows.insert(v, HashSet::new());
-        // let mut f = HashSet::new();
-        // let mut oldset = None;
-
-        // while Some(f.clone()) != oldset {
-        //     oldset = Some(f.clone());
-        //     #[allow(clippy::needless_collect)]
-        //     for (sym, rule) in self
-        //         .rules
-        //         .iter()
-        //         .map(|x| (x.symbol, x.tokens.to_vec()))
-        //         .collect::<Vec<_>>()
-        //     {
-        //         // print!("{} -> ", self.get_symbol(sym));
-        //         let mut set = HashSet::<Option<Token>>::new();
-        //         for i in 0..rule.len() {
-        //             // print!("{}: {}, ", self.get_grammar_symbol(rule[i]), rule[i] == GrammarSymbol::Symbol(v));
-        //             if rule[i] == GrammarSymbol::Symbol(v) {
-        //                 set.extend(self.first(&rule[i + 1..]).as_ref())
-        //             }
-        //             // self.print_set(&f);
-        //         }
-        //         // println!();
-
-        //         if set.contains(&None) {
-        //             f.extend(self.follow(sym).as_ref().iter().copied());
-        //         }
-        //         f.extend(set.into_iter().flatten().map(Some));
-        //     }
-        // }
-
-        // // println!();
-
-        // self.follows.insert(v, f.clone());
-        // Cow::Owned(f)
-    }
-
-    pub fn follows(&mut self) {
-        // Initialize all sets to an empty set
-        for symbol in 0..self.symbols.len() {
-            let symbol = Symbol(symbol);
-            self.follows.entry(symbol).or_default();
+        self.symbols.push(name);
+        Symbol(self.symbols.len() - 1)
+    }
+
+    /// Adds a symbol named after `like` with `suffix`, `E_tail` for `E`,
+    /// numbered from 2 when a symbol or token already has that name
+    pub(crate) fn add_symbol_suffixed(&mut self, like: Symbol, suffix: &str) -> Symbol {
+        let base = format!("{}_{suffix}", self.symbols[like.0]);
+        let mut name = base.clone();
+        let mut n = 1;
+        while self.symbols.contains(&name) || self.tokens.contains(&name) {
+            n += 1;
+            name = format!("{base}{n}");
         }
+        self.symbols.push(name);
+        Symbol(self.symbols.len() - 1)
+    }
 
-        let mut follows = self.follows.clone();
+    /// The rules before the axiom
+    pub(crate) fn written_rules(&self) -> &[Rule] {
+        &self.rules[..self.axiom.unwrap_or(self.rules.len())]
+    }
 
-        let mut oldsets = None;
-        while Some(&follows) != oldsets.as_ref() {
-            oldsets = Some(follows.clone());
+    /// Replaces the rules before the axiom, which stays last, and
+    /// recomputes the sets
+    pub(crate) fn set_written_rules(&mut self, mut rules: Vec<Rule>) {
+        if let Some(axiom) = self.axiom {
+            rules.push(self.rules[axiom].clone());
+            self.axiom = Some(rules.len() - 1);
+        }
+        self.rules = rules;
+        self.compute_sets();
+    }
+
+    /// Computes nullable, then FIRST and FOLLOW for every symbol. The sets
+    /// only grow along the "is included in" edges between symbols, so they
+    /// are seeded with the tokens read directly and then propagated with a
+    /// worklist.
+    fn compute_sets(&mut self) {
+        let n = self.symbols.len();
+        let mut nullable = vec![false; n];
+        let mut changed = true;
+        while changed {
+            changed = false;
             for rule in &self.rules {
-                for i in 0..rule.tokens.len() {
-                    if let GrammarSymbol::Symbol(b) = rule.tokens[i] {
-                        let mut f = self.first_non_mut(&rule.tokens[(i + 1)..]);
-                        if f.contains(&None) {
-                            f.remove(&None);
-                            let follows_a = follows.get(&rule.symbol).unwrap().clone();
-                            follows.get_mut(&b).unwrap().extend(follows_a);
+                if !nullable[rule.symbol.0]
+                    && rule.tokens.iter().all(|s| match s {
+                        GrammarSymbol::Token(_) => false,
+                        GrammarSymbol::Symbol(b) => nullable[b.0],
+                    })
+                {
+                    nullable[rule.symbol.0] = true;
+                    changed = true;
+                }
+            }
+        }
+        self.nullable = nullable;
+
+        // FIRST(B) ⊆ FIRST(A) if A -> α B β with α nullable
+        let mut firsts = vec![TokenSet::new(); n];
+        let mut edges = vec![Vec::new(); n];
+        for rule in &self.rules {
+            for symbol in &rule.tokens {
+                match *symbol {
+                    GrammarSymbol::Token(t) => {
+                        firsts[rule.symbol.0].insert(Some(t));
+                        break;
+                    }
+                    GrammarSymbol::Symbol(b) => {
+                        edges[b.0].push(rule.symbol.0);
+                        if !self.nullable[b.0] {
+                            break;
                         }
-                        follows.get_mut(&b).unwrap().extend(f);
                     }
                 }
             }
         }
+        propagate(&mut firsts, &edges);
+        self.firsts = firsts;
+
+        // FIRST(β) ⊆ FOLLOW(B) and, if β is nullable, FOLLOW(A) ⊆ FOLLOW(B)
+        // for every A -> α B β
+        let mut follows = vec![TokenSet::new(); n];
+        let mut edges = vec![Vec::new(); n];
+        if let Some(axiom) = self.axiom {
+            follows[self.rules[axiom].symbol.0].insert(None);
+        }
+        for rule in &self.rules {
+            for (i, symbol) in rule.tokens.iter().enumerate() {
+                if let GrammarSymbol::Symbol(b) = *symbol {
+                    let rest = &rule.tokens[i + 1..];
+                    follows[b.0].union_with(&self.first(rest));
+                    if self.derives_empty(rest) {
+                        edges[rule.symbol.0].push(b.0);
+                    }
+                }
+            }
+        }
+        propagate(&mut follows, &edges);
         self.follows = follows;
     }
 
-    pub fn print(&mut self) {
+    /// Whether `symbol` can derive the empty string
+    pub fn nullable(&self, symbol: Symbol) -> bool {
+        self.nullable[symbol.0]
+    }
+
+    /// Whether a string of grammar symbols can derive the empty string
+    pub fn derives_empty(&self, v: &[GrammarSymbol]) -> bool {
+        v.iter().all(|s| match s {
+            GrammarSymbol::Token(_) => false,
+            GrammarSymbol::Symbol(s) => self.nullable[s.0],
+        })
+    }
+
+    /// The tokens that can start a string derived from `symbol`
+    pub fn first_set(&self, symbol: Symbol) -> &TokenSet {
+        &self.firsts[symbol.0]
+    }
+
+    /// The tokens that can start a string derived from `v`. Whether it can
+    /// also be empty is answered by [`Grammar::derives_empty`]
+    pub fn first(&self, v: &[GrammarSymbol]) -> TokenSet {
+        let mut f = TokenSet::new();
+        for symbol in v {
+            match *symbol {
+                GrammarSymbol::Token(t) => {
+                    f.insert(Some(t));
+                    return f;
+                }
+                GrammarSymbol::Symbol(s) => {
+                    f.union_with(&self.firsts[s.0]);
+                    if !self.nullable[s.0] {
+                        return f;
+                    }
+                }
+            }
+        }
+        f
+    }
+
+    /// FIRST(v lookahead), the LR(1) lookahead of an item added by closure
+    pub fn first_followed_by(&self, v: &[GrammarSymbol], lookahead: &TokenSet) -> TokenSet {
+        let mut f = self.first(v);
+        if self.derives_empty(v) {
+            f.union_with(lookahead);
+        }
+        f
+    }
+
+    /// The tokens that can follow `symbol`, the end of input included
+    pub fn follow(&self, symbol: Symbol) -> &TokenSet {
+        &self.follows[symbol.0]
+    }
+
+    /// Prints the rules, then the symbol tables and sets. Rules are numbered
+    /// in the order they're written, the alternatives of `A -> x | y` from
+    /// left to right, and that number is the `ruleno` the templates get.
+    /// The rules generated for EBNF operators and the axiom `S' -> S` come
+    /// last.
+    pub fn print(&self) {
         println!("Grammar:");
         for (i, rule) in self.rules.iter().enumerate() {
             print!("{i:>4} {} -> ", self.get_symbol(rule.symbol));
-            let mut sems = rule.semantics.iter();
-            let mut toks = rule.tokens.iter();
-            while let (Some(sem), Some(tok)) = (sems.next(), toks.next()) {
+            for (sem, tok) in rule.semantics.iter().zip(&rule.tokens) {
                 if let Some(sem) = sem {
                     print!("{{{}}} ", self.get_semantic(*sem))
                 }
                 print!("{} ", self.get_grammar_symbol(*tok))
             }
-            if let Some(Some(last_sem)) = sems.next() {
+            if let Some(Some(last_sem)) = rule.semantics.get(rule.tokens.len()) {
                 print!("{{{}}} ", self.get_semantic(*last_sem))
             }
             if let Some(red) = rule.reduce_sem {
@@ -347,7 +599,18 @@ impl Grammar {
         println!();
         println!("Symbols:");
         for (i, tok) in self.symbols.iter().enumerate() {
-            println!("{i:>4} {tok}");
+            if self.start == Some(Symbol(i)) {
+                println!("{i:>4} {tok} (start)");
+            } else {
+                println!("{i:>4} {tok}");
+            }
+        }
+        println!();
+        println!("Precedences:");
+        for (i, tok) in self.tokens.iter().enumerate() {
+            if let Some(prec) = self.precedences.get(&Token(i)) {
+                println!("{:>4} {tok} {:?}", prec.level, prec.associativity);
+            }
         }
         println!();
         println!("Semantics:");
@@ -358,9 +621,11 @@ impl Grammar {
         println!("Firsts:");
         for i in 0..self.symbols.len() {
             print!("{:>4} = {{", self.get_symbol(Symbol(i)));
-            let first = self.first(&[GrammarSymbol::Symbol(Symbol(i))]);
-            for f in first.as_ref().clone() {
-                print!("{}, ", f.map_or("lambda", |x| self.get_token(x)))
+            for f in self.first_set(Symbol(i)).iter().flatten() {
+                print!("{}, ", self.get_token(f))
+            }
+            if self.nullable[i] {
+                print!("lambda, ")
             }
             println!("}}");
         }
@@ -369,8 +634,7 @@ impl Grammar {
         for i in 0..self.symbols.len() {
             print!("{:>4} = {{", self.get_symbol(Symbol(i)));
 
-            let follow = self.follow(Symbol(i));
-            for f in follow.as_ref().clone() {
+            for f in self.follow(Symbol(i)).iter() {
                 print!("{}, ", f.map_or("$", |x| self.get_token(x)))
             }
             println!("}}");
@@ -430,3 +694,85 @@ impl Grammar {
     //     }
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Writes `files` in a fresh directory and reads the first one
+    fn load(name: &str, files: &[(&str, &str)]) -> Result<Grammar> {
+        let dir = std::env::temp_dir().join(format!("grammar-gen-{}-{name}", std::process::id()));
+        for (path, text) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        let grammar = Grammar::from_file(&dir.join(files[0].0));
+        fs::remove_dir_all(&dir).unwrap();
+        grammar
+    }
+
+    fn error(result: Result<Grammar>) -> String {
+        match result {
+            Ok(_) => panic!("the grammar loaded"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn includes_are_relative_to_the_including_file() {
+        let g = load(
+            "relative",
+            &[
+                ("main.grammar", "%include \"sub/defs.grammar\"\nS -> T"),
+                ("sub/defs.grammar", "%include \"more.grammar\"\nT -> U t"),
+                ("sub/more.grammar", "U -> u"),
+            ],
+        )
+        .unwrap();
+        assert!(g.diagnostics().is_empty());
+        let heads = g.written_rules().iter().map(|r| g.get_symbol(r.symbol));
+        assert_eq!(heads.collect::<Vec<_>>(), ["U", "T", "S"]);
+        assert_eq!(g.start().map(|s| g.get_symbol(s)), Some("S"));
+    }
+
+    #[test]
+    fn include_cycles_load_each_file_once() {
+        let g = load(
+            "cycle",
+            &[
+                ("a.grammar", "%include \"b.grammar\"\nS -> T"),
+                ("b.grammar", "%include \"a.grammar\"\nT -> t"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(g.written_rules().len(), 2);
+    }
+
+    #[test]
+    fn files_must_not_contradict_each_other() {
+        let message = error(load(
+            "defined",
+            &[
+                ("a.grammar", "%include \"b.grammar\"\nS -> t"),
+                ("b.grammar", "S -> u"),
+            ],
+        ));
+        assert!(message.contains("non terminal S is defined in two files"));
+        let message = error(load(
+            "precedence",
+            &[
+                (
+                    "a.grammar",
+                    "%include \"b.grammar\"\n%left '+'\nS -> s '+' s",
+                ),
+                ("b.grammar", "%right '+'"),
+            ],
+        ));
+        assert!(message.contains("precedence of + declared twice"));
+        let message = error(load("missing", &[("a.grammar", "%include \"b.grammar\"")]));
+        assert!(message.contains("can't read"));
+    }
+}
@@ -0,0 +1,166 @@
+//! LALR(1) lookahead computation over an LR(0) automaton, following
+//! DeRemer & Pennello, "Efficient Computation of LALR(1) Look-Ahead Sets".
+
+use std::collections::HashMap;
+
+use crate::{
+    closures::AutomataState,
+    grammar::{Grammar, GrammarSymbol, Symbol},
+    token_set::TokenSet,
+};
+
+/// A nonterminal transition `(state, symbol)`. The transition with no symbol
+/// stands for the implicit start transition taken after reducing the axiom,
+/// which is only ever followed by the end of input.
+type Transition = (usize, Option<Symbol>);
+
+fn walk(states: &[&AutomataState], mut state: usize, symbols: &[GrammarSymbol]) -> usize {
+    for symbol in symbols {
+        state = match symbol {
+            GrammarSymbol::Token(t) => states[state].shift_actions[t],
+            GrammarSymbol::Symbol(s) => states[state].goto_actions[s],
+        };
+    }
+    state
+}
+
+/// Computes the LALR(1) lookahead set of every completed item, keyed by
+/// `(state, ruleno)`. `states` must be indexed by state number.
+pub fn lookaheads(
+    grammar: &Grammar,
+    states: &[&AutomataState],
+) -> HashMap<(usize, usize), TokenSet> {
+    let mut transitions: Vec<Transition> = vec![(0, None)];
+    for state in states {
+        for &symbol in state.goto_actions.keys() {
+            transitions.push((state.state, Some(symbol)));
+        }
+    }
+    let index = transitions
+        .iter()
+        .enumerate()
+        .map(|(i, &t)| (t, i))
+        .collect::<HashMap<_, _>>();
+    let axiom = grammar.axiom();
+    let rules_of = move |t: Transition| {
+        grammar
+            .get_rules()
+            .iter()
+            .enumerate()
+            .filter(move |(i, r)| match t.1 {
+                None => Some(*i) == axiom,
+                Some(s) => r.symbol == s,
+            })
+    };
+
+    // Direct reads: tokens shifted right after taking the transition
+    let direct_reads = transitions
+        .iter()
+        .map(|&(p, a)| match a {
+            None => TokenSet::from_iter([None]),
+            Some(a) => states[states[p].goto_actions[&a]]
+                .shift_actions
+                .keys()
+                .map(|&t| Some(t))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    // (p, A) reads (r, C) if p -A-> r -C-> and C is nullable
+    let reads = transitions
+        .iter()
+        .map(|&(p, a)| match a {
+            None => Vec::new(),
+            Some(a) => {
+                let r = states[p].goto_actions[&a];
+                states[r]
+                    .goto_actions
+                    .keys()
+                    .filter(|&&c| grammar.nullable(c))
+                    .map(|&c| index[&(r, Some(c))])
+                    .collect()
+            }
+        })
+        .collect::<Vec<_>>();
+    let read = digraph(&reads, direct_reads);
+
+    // (p, A) includes (p', B) if B -> β A γ, γ is nullable and p' -β-> p
+    let mut includes = vec![Vec::new(); transitions.len()];
+    for (b, &t) in transitions.iter().enumerate() {
+        for (_, rule) in rules_of(t) {
+            let mut p = t.0;
+            for (i, symbol) in rule.tokens.iter().enumerate() {
+                if let GrammarSymbol::Symbol(a) = *symbol {
+                    if grammar.derives_empty(&rule.tokens[i + 1..]) {
+                        includes[index[&(p, Some(a))]].push(b);
+                    }
+                }
+                p = walk(states, p, std::slice::from_ref(symbol));
+            }
+        }
+    }
+    let follow = digraph(&includes, read);
+
+    // A -> ω completed in q has lookback (p, A) if p -ω-> q
+    let mut lookaheads = HashMap::<_, TokenSet>::new();
+    for (i, &t) in transitions.iter().enumerate() {
+        for (ruleno, rule) in rules_of(t) {
+            let q = walk(states, t.0, &rule.tokens);
+            lookaheads
+                .entry((q, ruleno))
+                .or_default()
+                .union_with(&follow[i]);
+        }
+    }
+    lookaheads
+}
+
+/// Computes `F(x) = F'(x) ∪ ⋃ { F(y) | x R y }` for every node, collapsing
+/// strongly connected components as it goes.
+fn digraph(relation: &[Vec<usize>], mut sets: Vec<TokenSet>) -> Vec<TokenSet> {
+    struct Walker<'a> {
+        relation: &'a [Vec<usize>],
+        sets: Vec<TokenSet>,
+        depth: Vec<usize>,
+        stack: Vec<usize>,
+    }
+
+    impl Walker<'_> {
+        fn traverse(&mut self, x: usize) {
+            self.stack.push(x);
+            let d = self.stack.len();
+            self.depth[x] = d;
+            for &y in &self.relation[x] {
+                if self.depth[y] == 0 {
+                    self.traverse(y);
+                }
+                self.depth[x] = self.depth[x].min(self.depth[y]);
+                let fy = self.sets[y].clone();
+                self.sets[x].union_with(&fy);
+            }
+            if self.depth[x] == d {
+                while let Some(top) = self.stack.pop() {
+                    self.depth[top] = usize::MAX;
+                    if top == x {
+                        break;
+                    }
+                    self.sets[top] = self.sets[x].clone();
+                }
+            }
+        }
+    }
+
+    sets.resize_with(relation.len(), TokenSet::new);
+    let mut walker = Walker {
+        relation,
+        sets,
+        depth: vec![0; relation.len()],
+        stack: Vec::new(),
+    };
+    for x in 0..relation.len() {
+        if walker.depth[x] == 0 {
+            walker.traverse(x);
+        }
+    }
+    walker.sets
+}
@@ -0,0 +1,416 @@
+//! Tokenizer for grammar files.
+//!
+//! Words are split on whitespace as they always were, so `A -> b c {sem}`
+//! still lexes as a symbol, an arrow, two words and a semantic. On top of
+//! that:
+//!
+//! - `'...'` and `"..."` are literal terminals, which may contain spaces,
+//!   `->`, braces and the escapes `\\`, `\'`, `\"`, `\n`, `\t` and `\r`
+//! - `// ...` comments run to the end of the line and `/* ... */` comments
+//!   may span lines
+//! - `{...}` and `R{...}` run to their matching `}` on the line, so a
+//!   semantic may hold `;`, `->`, `,` and spaces, as in `{x; y}`
+//! - `->`, `;` and the start of a comment always end a word, so `A->b;`
+//!   is a rule too
+//! - a lone `|` separates alternatives
+//! - `(` and `)` group when written against what they enclose, as in
+//!   `(a b)`, and `?`, `*` and `+` are operators when written right after
+//!   a word, a quoted literal or a group, as in `Item*`
+//! - inside parentheses, `,` separates the arguments of a parameterized
+//!   nonterminal, as in `delimited('(', expr, ')')`
+//!
+//! so the terminals `;` and `|` have to be quoted, as does `,` in a group,
+//! while the spaced out `( E )` and `E * E` are still terminals. Line
+//! breaks aren't lexemes, the parser looks at where each lexeme starts
+//! instead.
+
+use crate::{
+    diagnostic::{Diagnostic, SourceFile, Span},
+    error::{Error, Result},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexemeKind {
+    /// A bare word, either a symbol or a terminal
+    Word(String),
+    /// A quoted literal, always a terminal
+    Quoted(String),
+    Arrow,
+    /// `|`, between alternatives
+    Pipe,
+    /// `;`, ending a rule
+    Semicolon,
+    /// `(` opening a group
+    Open,
+    /// `)` closing a group
+    Close,
+    /// `,` between the arguments or parameters of a parameterized
+    /// nonterminal
+    Comma,
+    /// Postfix `?`
+    Question,
+    /// Postfix `*`
+    Star,
+    /// Postfix `+`
+    Plus,
+    /// `{name}`
+    Semantic(String),
+    /// `R{name}`
+    ReduceSemantic(String),
+    /// `%name`
+    Directive(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lexeme {
+    pub kind: LexemeKind,
+    pub span: Span,
+}
+
+struct Lexer<'a> {
+    source: &'a SourceFile,
+    file: usize,
+    lexemes: Vec<Lexeme>,
+    /// Where the block comment being skipped started
+    in_comment: Option<Span>,
+    /// Where the last lexeme of the current line ended
+    last_end: Option<usize>,
+    /// How many parentheses are open in the current statement
+    depth: usize,
+}
+
+const POSTFIX: [char; 3] = ['?', '*', '+'];
+
+impl Lexer<'_> {
+    fn push(&mut self, kind: LexemeKind, line: usize, start: usize, end: usize) {
+        let span = self.source.span(self.file, line, start, end - start);
+        if start == 0 || kind == LexemeKind::Semicolon {
+            self.depth = 0;
+        }
+        match kind {
+            LexemeKind::Open => self.depth += 1,
+            LexemeKind::Close => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+        self.lexemes.push(Lexeme { kind, span });
+        self.last_end = Some(end);
+    }
+
+    /// Whether the last lexeme ends at `i` and a postfix operator there
+    /// applies to it
+    fn operand_ends_at(&self, i: usize) -> bool {
+        self.last_end == Some(i)
+            && self.lexemes.last().is_some_and(|l| {
+                matches!(
+                    l.kind,
+                    LexemeKind::Word(_)
+                        | LexemeKind::Quoted(_)
+                        | LexemeKind::Close
+                        | LexemeKind::Question
+                        | LexemeKind::Star
+                        | LexemeKind::Plus
+                )
+            })
+    }
+
+    fn error(&self, message: &str, line: usize, start: usize, end: usize) -> Error {
+        let span = self.source.span(self.file, line, start, end - start);
+        Diagnostic::error(message)
+            .at(self.source.location(span))
+            .into()
+    }
+
+    fn line(&mut self, line: usize) -> Result<()> {
+        let text = self.source.lines[line].as_str();
+        let mut i = 0;
+        self.last_end = None;
+        while i < text.len() {
+            let rest = &text[i..];
+            if self.in_comment.is_some() {
+                match rest.find("*/") {
+                    Some(end) => {
+                        self.in_comment = None;
+                        i += end + 2;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+            let c = rest.chars().next().unwrap();
+            if c.is_whitespace() {
+                i += c.len_utf8();
+            } else if rest.starts_with("//") {
+                break;
+            } else if rest.starts_with("/*") {
+                self.in_comment = Some(self.source.span(self.file, line, i, 2));
+                i += 2;
+            } else if rest.starts_with("->") {
+                self.push(LexemeKind::Arrow, line, i, i + 2);
+                i += 2;
+            } else if c == ';' {
+                self.push(LexemeKind::Semicolon, line, i, i + 1);
+                i += 1;
+            } else if c == '\'' || c == '"' {
+                i = self.quoted(line, i, c)?;
+            } else if c == '(' && rest[1..].starts_with(|c: char| !c.is_whitespace()) {
+                self.push(LexemeKind::Open, line, i, i + 1);
+                i += 1;
+            } else if c == ')' && self.last_end == Some(i) {
+                self.push(LexemeKind::Close, line, i, i + 1);
+                i += 1;
+            } else if c == ',' && self.depth > 0 {
+                self.push(LexemeKind::Comma, line, i, i + 1);
+                i += 1;
+            } else if POSTFIX.contains(&c) && self.operand_ends_at(i) {
+                let kind = match c {
+                    '?' => LexemeKind::Question,
+                    '*' => LexemeKind::Star,
+                    _ => LexemeKind::Plus,
+                };
+                self.push(kind, line, i, i + 1);
+                i += 1;
+            } else if let Some(len) = semantic_len(rest) {
+                let kind = match rest.strip_prefix('R') {
+                    Some(sem) => LexemeKind::ReduceSemantic(sem[1..len - 2].to_string()),
+                    None => LexemeKind::Semantic(rest[1..len - 1].to_string()),
+                };
+                self.push(kind, line, i, i + len);
+                i += len;
+            } else {
+                let len = rest.find(|c: char| c.is_whitespace()).unwrap_or(rest.len());
+                let mut len = [
+                    rest[..len].find("->"),
+                    rest[..len].find(';'),
+                    rest[c.len_utf8()..len]
+                        .find(['(', ')'])
+                        .map(|p| p + c.len_utf8()),
+                    rest[..len].find(',').filter(|_| self.depth > 0),
+                    rest[..len].find("//"),
+                    rest[..len].find("/*"),
+                ]
+                .into_iter()
+                .flatten()
+                .min()
+                .unwrap_or(len);
+                // `Item*`, but not `++`
+                if let [.., before, last] = rest.as_bytes()[..len] {
+                    if POSTFIX.contains(&char::from(last)) && !POSTFIX.contains(&char::from(before))
+                    {
+                        len -= 1;
+                    }
+                }
+                let word = &rest[..len];
+                let kind = if word == "|" {
+                    LexemeKind::Pipe
+                } else if let Some(sem) = word.strip_prefix("R{").and_then(|w| w.strip_suffix('}'))
+                {
+                    LexemeKind::ReduceSemantic(sem.to_string())
+                } else if let Some(sem) = word.strip_prefix('{').and_then(|w| w.strip_suffix('}')) {
+                    LexemeKind::Semantic(sem.to_string())
+                } else if let Some(directive) = word.strip_prefix('%').filter(|d| !d.is_empty()) {
+                    LexemeKind::Directive(directive.to_string())
+                } else {
+                    LexemeKind::Word(word.to_string())
+                };
+                self.push(kind, line, i, i + len);
+                i += len;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lexes a quoted literal starting at `start`, returning where it ends
+    fn quoted(&mut self, line: usize, start: usize, quote: char) -> Result<usize> {
+        let text = self.source.lines[line].as_str();
+        let mut value = String::new();
+        let mut chars = text[start + 1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            let i = start + 1 + i;
+            match c {
+                '\\' => {
+                    let Some((j, escaped)) = chars.next() else {
+                        break;
+                    };
+                    value.push(match escaped {
+                        '\\' | '\'' | '"' => escaped,
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        _ => {
+                            let end = start + 1 + j + escaped.len_utf8();
+                            return Err(self.error("unknown escape sequence", line, i, end));
+                        }
+                    });
+                }
+                c if c == quote => {
+                    self.push(LexemeKind::Quoted(value), line, start, i + 1);
+                    return Ok(i + 1);
+                }
+                c => value.push(c),
+            }
+        }
+        Err(self.error("unterminated quoted terminal", line, start, text.len()))
+    }
+}
+
+/// The length of the `{...}` or `R{...}` semantic `rest` starts with, up
+/// to its matching `}`, if it's closed on this line
+fn semantic_len(rest: &str) -> Option<usize> {
+    let open = if rest.starts_with("R{") {
+        1
+    } else if rest.starts_with('{') {
+        0
+    } else {
+        return None;
+    };
+    let mut depth = 0;
+    for (i, c) in rest[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits a grammar source into lexemes
+pub fn lex(source: &SourceFile, file: usize) -> Result<Vec<Lexeme>> {
+    let mut lexer = Lexer {
+        source,
+        file,
+        lexemes: Vec::new(),
+        in_comment: None,
+        last_end: None,
+        depth: 0,
+    };
+    for line in 0..source.lines.len() {
+        lexer.line(line)?;
+    }
+    if let Some(span) = lexer.in_comment {
+        return Err(Diagnostic::error("unterminated block comment")
+            .at(source.location(span))
+            .into());
+    }
+    Ok(lexer.lexemes)
+}
+
+/// Writes a terminal as a quoted literal that lexes back to `value`
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in value.chars() {
+        match c {
+            '\\' | '\'' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Writes lexemes back as grammar source, the way generated symbols are
+/// named after the groups they stand for
+pub fn text(lexemes: &[Lexeme]) -> String {
+    let mut text = String::new();
+    let mut attach = true;
+    for lexeme in lexemes {
+        let written = match &lexeme.kind {
+            LexemeKind::Word(word) => word.clone(),
+            LexemeKind::Quoted(value) => quote(value),
+            LexemeKind::Arrow => "->".to_string(),
+            LexemeKind::Pipe => "|".to_string(),
+            LexemeKind::Semicolon => ";".to_string(),
+            LexemeKind::Open => "(".to_string(),
+            LexemeKind::Close => ")".to_string(),
+            LexemeKind::Comma => ",".to_string(),
+            LexemeKind::Question => "?".to_string(),
+            LexemeKind::Star => "*".to_string(),
+            LexemeKind::Plus => "+".to_string(),
+            LexemeKind::Semantic(sem) => format!("{{{sem}}}"),
+            LexemeKind::ReduceSemantic(sem) => format!("R{{{sem}}}"),
+            LexemeKind::Directive(directive) => format!("%{directive}"),
+        };
+        let attached = matches!(
+            lexeme.kind,
+            LexemeKind::Close
+                | LexemeKind::Comma
+                | LexemeKind::Question
+                | LexemeKind::Star
+                | LexemeKind::Plus
+        );
+        if !attach && !attached {
+            text.push(' ');
+        }
+        text.push_str(&written);
+        attach = lexeme.kind == LexemeKind::Open;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<LexemeKind> {
+        let source = SourceFile {
+            name: "test".to_string(),
+            lines: text.lines().map(str::to_string).collect(),
+        };
+        lex(&source, 0)
+            .unwrap()
+            .into_iter()
+            .map(|l| l.kind)
+            .collect()
+    }
+
+    fn word(w: &str) -> LexemeKind {
+        LexemeKind::Word(w.to_string())
+    }
+
+    #[test]
+    fn comments_end_words() {
+        let rule = vec![word("S"), LexemeKind::Arrow, word("a"), word("b")];
+        assert_eq!(kinds("S -> a b// comment"), rule);
+        assert_eq!(kinds("S -> a b/*x*/"), rule);
+        assert_eq!(kinds("S -> a/* x */b"), rule);
+    }
+
+    #[test]
+    fn semantics_run_to_their_brace() {
+        assert_eq!(
+            kinds("S -> a {x; y} b R{f(a, b) -> c}"),
+            vec![
+                word("S"),
+                LexemeKind::Arrow,
+                word("a"),
+                LexemeKind::Semantic("x; y".to_string()),
+                word("b"),
+                LexemeKind::ReduceSemantic("f(a, b) -> c".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds("S -> {a{b}c};"),
+            vec![
+                word("S"),
+                LexemeKind::Arrow,
+                LexemeKind::Semantic("a{b}c".to_string()),
+                LexemeKind::Semicolon,
+            ]
+        );
+        assert_eq!(
+            kinds("S -> {x"),
+            vec![word("S"), LexemeKind::Arrow, word("{x")]
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! LR parse table generator: reads a grammar, builds an SLR, LALR(1),
+//! Pager's minimal LR(1) or canonical LR(1) automaton for it and renders
+//! the tables through user templates.
+
+pub mod analysis;
+pub mod closures;
+pub mod config;
+pub mod conflicts;
+pub mod context;
+pub mod diagnostic;
+pub mod error;
+mod export;
+pub mod grammar;
+mod lalr;
+pub mod lexer;
+pub mod ll;
+mod parser;
+pub mod render;
+pub mod token_set;
+mod transform;
+mod yacc;
+
+pub use closures::{Automata, AutomataKind, AutomataState, Item};
+pub use config::Config;
+pub use error::{Error, Result};
+pub use grammar::Grammar;
+pub use render::render;
@@ -0,0 +1,203 @@
+//! LL(1) analysis: the predict table of a top-down parser, from the same
+//! FIRST and FOLLOW sets the LR automata use.
+//!
+//! Rule `A -> α` is predicted on the tokens of FIRST(α), and on FOLLOW(A)
+//! too when α derives the empty string. Where two rules of `A` are
+//! predicted on the same token the grammar isn't LL(1), and the table keeps
+//! the rule that comes first, like a reduce/reduce conflict.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    diagnostic::Diagnostic,
+    grammar::{Grammar, Symbol, Token},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LlConflictKind {
+    /// Both rules can start with the token
+    FirstFirst,
+    /// One rule can start with the token, the other derives the empty
+    /// string and the token can follow the symbol
+    FirstFollow,
+}
+
+/// Two rules of a symbol predicted on the same tokens
+#[derive(Debug, Clone)]
+pub struct LlConflict {
+    pub kind: LlConflictKind,
+    pub symbol: Symbol,
+    /// The rule kept in the table and the one left out
+    pub rules: [usize; 2],
+    /// The lookaheads, `None` being the end of input
+    pub tokens: Vec<Option<Token>>,
+}
+
+impl LlConflict {
+    pub fn diagnostic(&self, grammar: &Grammar) -> Diagnostic {
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|t| t.map_or("$", |t| grammar.get_token(t)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let symbol = grammar.get_symbol(self.symbol);
+        let [kept, left_out] = self.rules;
+        let (kind, note) = match self.kind {
+            LlConflictKind::FirstFirst => ("FIRST/FIRST", "both rules can start with them"),
+            LlConflictKind::FirstFollow => (
+                "FIRST/FOLLOW",
+                "one rule derives the empty string and they can follow the symbol",
+            ),
+        };
+        let rule = &grammar.get_rules()[left_out];
+        Diagnostic::warning(format!(
+            "{kind} conflict for {symbol} on {tokens} between rule {kept} and rule {left_out}, predicting rule {kept}"
+        ))
+        .at(grammar.locate(rule.span))
+        .note(note)
+    }
+}
+
+/// The rule to predict for each symbol and lookahead
+#[derive(Debug, Clone, Default)]
+pub struct PredictTable {
+    entries: BTreeMap<(Symbol, Option<Token>), usize>,
+    conflicts: Vec<LlConflict>,
+}
+
+impl PredictTable {
+    pub fn new(grammar: &Grammar) -> Self {
+        let mut table = Self::default();
+        for (ruleno, rule) in grammar.get_rules().iter().enumerate() {
+            if Some(ruleno) == grammar.axiom() {
+                continue;
+            }
+            let first = grammar.first(&rule.tokens);
+            let mut predict = first.clone();
+            if grammar.derives_empty(&rule.tokens) {
+                predict.union_with(grammar.follow(rule.symbol));
+            }
+            for token in predict.iter() {
+                let kept = *table.entries.entry((rule.symbol, token)).or_insert(ruleno);
+                if kept == ruleno {
+                    continue;
+                }
+                let kept_first = grammar.first(&grammar.get_rules()[kept].tokens);
+                let kind = if first.contains(token) && kept_first.contains(token) {
+                    LlConflictKind::FirstFirst
+                } else {
+                    LlConflictKind::FirstFollow
+                };
+                let rules = [kept, ruleno];
+                match table
+                    .conflicts
+                    .iter_mut()
+                    .find(|c| c.rules == rules && c.kind == kind)
+                {
+                    Some(conflict) => conflict.tokens.push(token),
+                    None => table.conflicts.push(LlConflict {
+                        kind,
+                        symbol: rule.symbol,
+                        rules,
+                        tokens: vec![token],
+                    }),
+                }
+            }
+        }
+        table
+    }
+
+    /// The entries, by symbol then lookahead with the end of input first
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, Option<Token>, usize)> + '_ {
+        self.entries.iter().map(|(&(s, t), &r)| (s, t, r))
+    }
+
+    pub fn get(&self, symbol: Symbol, token: Option<Token>) -> Option<usize> {
+        self.entries.get(&(symbol, token)).copied()
+    }
+
+    pub fn conflicts(&self) -> &[LlConflict] {
+        &self.conflicts
+    }
+
+    pub fn print(&self, grammar: &Grammar) {
+        println!();
+        println!("PREDICT TABLE");
+        for (symbol, token, ruleno) in self.iter() {
+            println!(
+                "{} on {} -> Rule {ruleno}",
+                grammar.get_symbol(symbol),
+                token.map_or("$", |t| grammar.get_token(t))
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    fn table(lines: &[&str]) -> (Grammar, PredictTable) {
+        let grammar = Grammar::new(lines.iter().map(|l| Cow::Borrowed(*l))).unwrap();
+        let table = PredictTable::new(&grammar);
+        (grammar, table)
+    }
+
+    /// The kind, rules and lookaheads of each conflict
+    fn conflicts(
+        grammar: &Grammar,
+        table: &PredictTable,
+    ) -> Vec<(LlConflictKind, [usize; 2], Vec<String>)> {
+        table
+            .conflicts()
+            .iter()
+            .map(|c| {
+                let tokens = c
+                    .tokens
+                    .iter()
+                    .map(|t| t.map_or("$", |t| grammar.get_token(t)).to_string());
+                (c.kind, c.rules, tokens.collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_first() {
+        let (grammar, table) = table(&["E -> T '+' E | T", "T -> id | '(' E ')'"]);
+        assert_eq!(
+            conflicts(&grammar, &table),
+            [(
+                LlConflictKind::FirstFirst,
+                [0, 1],
+                vec!["id".to_string(), "(".to_string()]
+            )]
+        );
+        let e = grammar.get_rules()[0].symbol;
+        let id = grammar
+            .iter_tokens()
+            .find(|&t| grammar.get_token(t) == "id");
+        assert_eq!(table.get(e, id), Some(0));
+    }
+
+    #[test]
+    fn first_follow() {
+        let (grammar, table) = table(&["S -> A a | b", "A -> a", "A ->"]);
+        assert_eq!(
+            conflicts(&grammar, &table),
+            [(LlConflictKind::FirstFollow, [2, 3], vec!["a".to_string()])]
+        );
+    }
+
+    #[test]
+    fn ll1_grammar() {
+        let (grammar, table) = table(&["E -> T E'", "E' -> '+' T E' |", "T -> id | '(' E ')'"]);
+        assert!(table.conflicts().is_empty());
+        // E' on `)` and at the end of the input predicts the empty rule
+        let rest = grammar.get_rules()[2].symbol;
+        assert_eq!(table.get(rest, None), Some(2));
+        assert_eq!(table.iter().filter(|&(s, _, _)| s == rest).count(), 3);
+    }
+}
@@ -0,0 +1,1154 @@
+//! Turns the lexemes of a grammar file into rules.
+//!
+//! Every statement is either a declaration or a rule `A -> x | y`, with one
+//! [`Rule`] per alternative. The declarations are
+//!
+//! - `%left`, `%right` and `%nonassoc`, giving tokens a precedence, each
+//!   line binding tighter than the ones before, and `%precedence`, giving
+//!   them a level without an associativity
+//! - `%token`, declaring tokens without a precedence
+//! - `%start S`, starting from `S` instead of the first rule's symbol, not
+//!   counting the rules of included files
+//! - `%strict`, making it an error to use a bare word terminal that no
+//!   declaration names, so a misspelt nonterminal isn't taken for a token
+//! - `%include "file"`, reading another grammar file in its place, handled
+//!   before parsing by [`Grammar`](crate::Grammar)
+//!
+//! A statement ends at a `;` or at the next line that isn't indented,
+//! unless that line starts with `|`:
+//!
+//! ```text
+//! List -> List ',' Item
+//!       | Item
+//! Item -> x; Item -> y
+//! ```
+//!
+//! A bare word in a body is a nonterminal if some rule has it on its
+//! left-hand side and a terminal otherwise, a quoted literal is always a
+//! terminal.
+//!
+//! Several files parse as one grammar, as long as they don't contradict
+//! each other: a nonterminal gets its rules from a single file, a token
+//! gets at most one precedence and there's at most one start symbol.
+//!
+//! EBNF operators are desugared into generated symbols, named after what
+//! they repeat so using the same one twice shares its rules:
+//!
+//! - `X?` is `option(X) -> | X`
+//! - `X*` is `list(X) -> | list(X) X`
+//! - `X+` is `nonempty_list(X) -> X | nonempty_list(X) X`
+//! - `X % ','` is `separated_nonempty_list(',', X) -> X | ... ',' X`
+//! - `(a b | c)` is `(a b | c) -> a b | c`
+//!
+//! Lists are left recursive, which keeps the LR stack flat. Semantics inside
+//! a group belong to the group's rules, semantics around an operator to the
+//! rule it's written in.
+//!
+//! A nonterminal can take parameters, and is then expanded for every list
+//! of arguments it's used with:
+//!
+//! ```text
+//! delimited(open, X, close) -> open X close
+//! comma_list(X) -> X | comma_list(X) ',' X
+//! Call -> id delimited('(', comma_list(Expr), ')')
+//! ```
+//!
+//! gives the symbols `comma_list(Expr)` and
+//! `delimited('(', comma_list(Expr), ')')`.
+//! An argument is a single element, so a sequence has to be a group. The
+//! symbols the operators stand for can be used that way too, along with
+//! `separated_list(sep, X)`, which may be empty.
+
+use std::{
+    collections::{HashMap, HashSet},
+    slice::Iter,
+};
+
+use crate::{
+    diagnostic::{Diagnostic, Location, SourceFile, Span},
+    error::Result,
+    grammar::{Associativity, GrammarSymbol, Precedence, Rule, Semantic, Symbol, Token},
+    lexer::{self, Lexeme, LexemeKind},
+};
+
+/// Everything read from the grammar files, before the sets are computed
+#[derive(Debug, Default)]
+pub(crate) struct Parsed {
+    pub symbols: Vec<String>,
+    pub tokens: Vec<String>,
+    pub semantics: Vec<String>,
+    pub rules: Vec<Rule>,
+    pub precedences: HashMap<Token, Precedence>,
+    /// From `%start`, or else the left-hand side of the first rule written
+    /// in the first file
+    pub start: Option<Symbol>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+fn index_of(names: &mut Vec<String>, name: &str) -> usize {
+    names.iter().position(|n| n == name).unwrap_or_else(|| {
+        names.push(name.to_string());
+        names.len() - 1
+    })
+}
+
+/// A symbol read in a rule body, with the name generated symbols built
+/// from it get
+#[derive(Clone)]
+struct Element {
+    symbol: GrammarSymbol,
+    span: Span,
+    name: String,
+}
+
+/// The standard parameterized nonterminals and how many arguments they
+/// take
+const STANDARD: [(&str, usize); 5] = [
+    ("option", 1),
+    ("list", 1),
+    ("nonempty_list", 1),
+    ("separated_nonempty_list", 2),
+    ("separated_list", 2),
+];
+
+/// How deep expansions can nest before `f(X) -> f(list(X))` is taken for
+/// an endless expansion
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+/// A parameterized nonterminal, with the parameter names, head and body of
+/// each of its statements
+#[derive(Clone)]
+struct Macro<'a> {
+    definitions: Vec<(Vec<String>, Span, &'a [Lexeme])>,
+}
+
+/// Where the group starting with `rest`, right after its `(`, ends
+fn group_end(rest: &[Lexeme]) -> Option<usize> {
+    let mut depth = 0usize;
+    rest.iter().position(|l| match l.kind {
+        LexemeKind::Open => {
+            depth += 1;
+            false
+        }
+        LexemeKind::Close if depth == 0 => true,
+        LexemeKind::Close => {
+            depth -= 1;
+            false
+        }
+        _ => false,
+    })
+}
+
+struct Parser<'a> {
+    sources: &'a [SourceFile],
+    parsed: Parsed,
+    /// Rules of the symbols generated for EBNF operators, numbered after
+    /// the rules that were written
+    generated: Vec<Rule>,
+    /// Tokens named by `%token` or a precedence declaration
+    declared: HashSet<Token>,
+    /// Whether `%strict` makes undeclared bare word terminals an error
+    strict: bool,
+    /// Where each token got its precedence
+    precedence_spans: HashMap<Token, Span>,
+    /// Where the start symbol was declared
+    start_span: Option<Span>,
+    /// Where each nonterminal got its first rule, to catch two files
+    /// defining the same one
+    defined_at: HashMap<String, Span>,
+    macros: HashMap<String, Macro<'a>>,
+    /// The arguments of the parameterized nonterminal being expanded
+    arguments: HashMap<String, Element>,
+    /// How many expansions are nested
+    expanding: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn warn(&mut self, message: impl Into<String>, span: Span) {
+        self.parsed
+            .diagnostics
+            .push(Diagnostic::warning(message).at(self.location(span)));
+    }
+
+    fn error(&self, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic::error(message).at(self.location(span))
+    }
+
+    fn location(&self, span: Span) -> Option<Location> {
+        self.sources.get(span.file)?.location(span)
+    }
+
+    /// `span` as `file:line:col`, for notes pointing at a second place
+    fn position(&self, span: Span) -> String {
+        let file = self.sources.get(span.file).map_or("", |s| s.name.as_str());
+        format!("{file}:{}:{}", span.line, span.column)
+    }
+
+    /// Records that `name` has rules at `head`, failing if another file
+    /// already gave it some
+    fn define(&mut self, name: &str, head: Span) -> Result<()> {
+        let first = *self.defined_at.entry(name.to_string()).or_insert(head);
+        if first.file != head.file {
+            return Err(self
+                .error(format!("non terminal {name} is defined in two files"), head)
+                .note(format!("it was first defined at {}", self.position(first)))
+                .into());
+        }
+        Ok(())
+    }
+
+    /// Records a statement `name(params) -> body` of a parameterized
+    /// nonterminal
+    fn define_macro(
+        &mut self,
+        name: &str,
+        head: Span,
+        params: &[Lexeme],
+        body: &'a [Lexeme],
+    ) -> Result<()> {
+        if STANDARD.iter().any(|&(n, _)| n == name) {
+            return Err(self
+                .error(
+                    format!("{name} is a standard parameterized non terminal"),
+                    head,
+                )
+                .note("it can't be redefined")
+                .into());
+        }
+        let mut names: Vec<String> = Vec::new();
+        let mut expect_name = true;
+        for lexeme in params {
+            match &lexeme.kind {
+                LexemeKind::Word(param) if expect_name => {
+                    if names.contains(param) {
+                        return Err(self
+                            .error(format!("duplicate parameter {param}"), lexeme.span)
+                            .into());
+                    }
+                    names.push(param.clone());
+                }
+                LexemeKind::Comma if !expect_name => {}
+                _ => {
+                    return Err(self
+                        .error("expected parameter names separated by `,`", lexeme.span)
+                        .into())
+                }
+            }
+            expect_name = !expect_name;
+        }
+        if expect_name {
+            return Err(self
+                .error("expected a parameter name", head)
+                .note("a non terminal without parameters is written without parentheses")
+                .into());
+        }
+        self.define(name, head)?;
+        let arity = names.len();
+        let definitions = &mut self
+            .macros
+            .entry(name.to_string())
+            .or_insert(Macro {
+                definitions: Vec::new(),
+            })
+            .definitions;
+        if let Some((first, first_head, _)) = definitions.first() {
+            if first.len() != arity {
+                let first_head = *first_head;
+                let expected = first.len();
+                return Err(self
+                    .error(
+                        format!("{name} needs the same number of parameters everywhere"),
+                        head,
+                    )
+                    .note(format!(
+                        "it has {expected} at {}",
+                        self.position(first_head)
+                    ))
+                    .into());
+            }
+        }
+        definitions.push((names, head, body));
+        Ok(())
+    }
+
+    fn token(&mut self, name: &str) -> Token {
+        Token(index_of(&mut self.parsed.tokens, name))
+    }
+
+    fn semantic(&mut self, name: &str) -> Semantic {
+        Semantic(index_of(&mut self.parsed.semantics, name))
+    }
+
+    /// The tokens listed after `%left`, `%token` and the like, which count
+    /// as declared from then on
+    fn declared_tokens(&mut self, what: &str, line: &[Lexeme]) -> Vec<(Token, Span)> {
+        let mut tokens = Vec::new();
+        for lexeme in line {
+            match &lexeme.kind {
+                LexemeKind::Word(word) if self.parsed.symbols.contains(word) => self.warn(
+                    format!("{what} declared for non terminal {word}, ignoring"),
+                    lexeme.span,
+                ),
+                LexemeKind::Word(tok) | LexemeKind::Quoted(tok) => {
+                    let tok = self.token(tok);
+                    self.declared.insert(tok);
+                    tokens.push((tok, lexeme.span));
+                }
+                _ => self.warn("expected a token, ignoring", lexeme.span),
+            }
+        }
+        tokens
+    }
+
+    fn start(&mut self, span: Span, line: &[Lexeme]) -> Result<()> {
+        let [Lexeme {
+            kind: LexemeKind::Word(word),
+            span: word_span,
+        }] = line
+        else {
+            return Err(self
+                .error("expected a single symbol after %start", span)
+                .into());
+        };
+        let Some(symbol) = self.parsed.symbols.iter().position(|s| s == word) else {
+            return Err(self
+                .error(format!("start symbol {word} has no rules"), *word_span)
+                .into());
+        };
+        match (self.parsed.start, self.start_span) {
+            (Some(start), Some(first)) if start != Symbol(symbol) => {
+                return Err(self
+                    .error(
+                        format!(
+                            "start symbol {word} conflicts with {}",
+                            self.parsed.symbols[start.0]
+                        ),
+                        *word_span,
+                    )
+                    .note(format!(
+                        "the start symbol was declared at {}",
+                        self.position(first)
+                    ))
+                    .into());
+            }
+            (Some(_), _) => self.warn("start symbol declared twice, ignoring", span),
+            _ => {
+                self.parsed.start = Some(Symbol(symbol));
+                self.start_span = Some(span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a symbol to the end of `rule`, after a semantic if `after_sem`
+    fn push(&self, rule: &mut Rule, symbol: GrammarSymbol, span: Span, after_sem: bool) {
+        if let GrammarSymbol::Token(t) = symbol {
+            rule.precedence = self.parsed.precedences.get(&t).copied().or(rule.precedence);
+        }
+        rule.tokens.push(symbol);
+        rule.token_spans.push(span);
+        if !after_sem {
+            rule.semantics.push(None);
+            rule.semantic_spans.push(None);
+        }
+    }
+
+    /// A rule written by the parser rather than the user
+    fn synthetic(&self, symbol: Symbol, span: Span, body: &[(GrammarSymbol, Span)]) -> Rule {
+        let mut rule = Rule::new(symbol, span);
+        for &(s, span) in body {
+            self.push(&mut rule, s, span, false);
+        }
+        rule
+    }
+
+    /// The generated symbol `name`, its rules built by `rules` the first
+    /// time it's used so repeating `Item*` doesn't repeat its rules
+    fn generated(
+        &mut self,
+        name: String,
+        span: Span,
+        rules: impl FnOnce(&mut Self, Symbol) -> Result<Vec<Rule>>,
+    ) -> Result<Element> {
+        let symbol = match self.parsed.symbols.iter().position(|s| *s == name) {
+            Some(s) => Symbol(s),
+            None => {
+                let symbol = Symbol(index_of(&mut self.parsed.symbols, &name));
+                let rules = rules(self, symbol)?;
+                self.generated.extend(rules);
+                symbol
+            }
+        };
+        Ok(Element {
+            symbol: GrammarSymbol::Symbol(symbol),
+            span,
+            name,
+        })
+    }
+
+    /// Reads a word, a quoted literal or a group
+    fn primary(&mut self, lexemes: &mut Iter<'_, Lexeme>) -> Result<Element> {
+        let lexeme = lexemes.next().expect("an element starts here");
+        match &lexeme.kind {
+            LexemeKind::Word(word) => {
+                if let Some(argument) = self.arguments.get(word) {
+                    return Ok(Element {
+                        span: lexeme.span,
+                        ..argument.clone()
+                    });
+                }
+                let plain = self.parsed.symbols.contains(word);
+                let arity = match self.macros.get(word) {
+                    Some(m) => Some(m.definitions[0].0.len()),
+                    None => STANDARD
+                        .iter()
+                        .find(|&&(n, _)| n == word && !plain)
+                        .map(|&(_, arity)| arity),
+                };
+                if let Some(arity) = arity {
+                    let end = lexeme.span.column + lexeme.span.len;
+                    let applied = lexemes.as_slice().first().is_some_and(|l| {
+                        l.kind == LexemeKind::Open
+                            && l.span.file == lexeme.span.file
+                            && l.span.line == lexeme.span.line
+                            && l.span.column == end
+                    });
+                    if applied {
+                        return self.application(word, arity, lexeme, lexemes);
+                    }
+                    if self.macros.contains_key(word) {
+                        let s = if arity == 1 { "" } else { "s" };
+                        return Err(self
+                            .error(format!("{word} needs {arity} argument{s}"), lexeme.span)
+                            .note(format!("write it as {word}(...)"))
+                            .into());
+                    }
+                }
+                let symbol = match self.parsed.symbols.iter().position(|s| s == word) {
+                    Some(s) => GrammarSymbol::Symbol(Symbol(s)),
+                    None => {
+                        let tok = self.token(word);
+                        if self.strict && !self.declared.contains(&tok) {
+                            return Err(self
+                                .error(format!("undeclared terminal {word}"), lexeme.span)
+                                .note("declare it with %token, or give it rules if it's a non terminal")
+                                .into());
+                        }
+                        GrammarSymbol::Token(tok)
+                    }
+                };
+                Ok(Element {
+                    symbol,
+                    span: lexeme.span,
+                    name: word.clone(),
+                })
+            }
+            LexemeKind::Quoted(value) => Ok(Element {
+                symbol: GrammarSymbol::Token(self.token(value)),
+                span: lexeme.span,
+                name: lexer::quote(value),
+            }),
+            LexemeKind::Open => {
+                let rest = lexemes.as_slice();
+                let Some(end) = group_end(rest) else {
+                    return Err(self.error("unclosed group", lexeme.span).into());
+                };
+                lexemes.nth(end);
+                let inner = &rest[..end];
+                let span = lexeme.span.to(rest[end].span);
+                let name = format!("({})", lexer::text(inner));
+                self.generated(name, span, |p, s| p.alternatives(s, lexeme.span, inner))
+            }
+            _ => unreachable!("only called on the start of an element"),
+        }
+    }
+
+    /// Reads a primary followed by any number of `?`, `*` and `+`
+    fn repeated(&mut self, lexemes: &mut Iter<'_, Lexeme>) -> Result<Element> {
+        let mut element = self.primary(lexemes)?;
+        while let Some(op) = lexemes.as_slice().first().filter(|l| {
+            matches!(
+                l.kind,
+                LexemeKind::Question | LexemeKind::Star | LexemeKind::Plus
+            )
+        }) {
+            lexemes.next();
+            let span = element.span.to(op.span);
+            let name = match op.kind {
+                LexemeKind::Question => "option",
+                LexemeKind::Star => "list",
+                _ => "nonempty_list",
+            };
+            element = self.standard(name, &[element], span)?;
+        }
+        Ok(element)
+    }
+
+    /// Reads a repeated primary, and the separator after it in
+    /// `item % ","`. A `%` not followed by a quoted literal or a group is
+    /// just a terminal.
+    fn element(&mut self, lexemes: &mut Iter<'_, Lexeme>) -> Result<Element> {
+        let element = self.repeated(lexemes)?;
+        let [Lexeme {
+            kind: LexemeKind::Word(percent),
+            ..
+        }, separator, ..] = lexemes.as_slice()
+        else {
+            return Ok(element);
+        };
+        if percent != "%" || !matches!(separator.kind, LexemeKind::Quoted(_) | LexemeKind::Open) {
+            return Ok(element);
+        }
+        lexemes.next();
+        let separator = self.repeated(lexemes)?;
+        let span = element.span.to(separator.span);
+        self.standard("separated_nonempty_list", &[separator, element], span)
+    }
+
+    /// The standard parameterized nonterminal `name` for `args`, which the
+    /// EBNF operators also stand for
+    fn standard(&mut self, name: &str, args: &[Element], span: Span) -> Result<Element> {
+        let names = args.iter().map(|a| a.name.as_str()).collect::<Vec<_>>();
+        let full_name = format!("{name}({})", names.join(", "));
+        let symbols = args.iter().map(|a| (a.symbol, a.span)).collect::<Vec<_>>();
+        match (name, &symbols[..]) {
+            ("option", &[item]) => self.generated(full_name, span, |p, s| {
+                Ok(vec![
+                    p.synthetic(s, span, &[]),
+                    p.synthetic(s, span, &[item]),
+                ])
+            }),
+            ("list", &[item]) => self.generated(full_name, span, |p, s| {
+                let list = (GrammarSymbol::Symbol(s), span);
+                Ok(vec![
+                    p.synthetic(s, span, &[]),
+                    p.synthetic(s, span, &[list, item]),
+                ])
+            }),
+            ("nonempty_list", &[item]) => self.generated(full_name, span, |p, s| {
+                let list = (GrammarSymbol::Symbol(s), span);
+                Ok(vec![
+                    p.synthetic(s, span, &[item]),
+                    p.synthetic(s, span, &[list, item]),
+                ])
+            }),
+            ("separated_nonempty_list", &[sep, item]) => self.generated(full_name, span, |p, s| {
+                let list = (GrammarSymbol::Symbol(s), span);
+                Ok(vec![
+                    p.synthetic(s, span, &[item]),
+                    p.synthetic(s, span, &[list, sep, item]),
+                ])
+            }),
+            ("separated_list", _) => self.generated(full_name, span, |p, s| {
+                let list = p.standard("separated_nonempty_list", args, span)?;
+                Ok(vec![
+                    p.synthetic(s, span, &[]),
+                    p.synthetic(s, span, &[(list.symbol, span)]),
+                ])
+            }),
+            _ => unreachable!("arity is checked against STANDARD"),
+        }
+    }
+
+    /// Reads the arguments of `name(...)`, from the `(`, and expands it
+    fn application(
+        &mut self,
+        name: &str,
+        arity: usize,
+        head: &Lexeme,
+        lexemes: &mut Iter<'_, Lexeme>,
+    ) -> Result<Element> {
+        let open = lexemes.next().expect("the arguments start here");
+        let rest = lexemes.as_slice();
+        let Some(end) = group_end(rest) else {
+            return Err(self.error("unclosed argument list", open.span).into());
+        };
+        lexemes.nth(end);
+        let span = head.span.to(rest[end].span);
+        let mut args = Vec::new();
+        let mut depth = 0usize;
+        let mut start = 0;
+        for (i, lexeme) in rest[..=end].iter().enumerate() {
+            match lexeme.kind {
+                LexemeKind::Open => depth += 1,
+                LexemeKind::Close if depth > 0 => depth -= 1,
+                LexemeKind::Comma | LexemeKind::Close if depth == 0 => {
+                    args.push(self.argument(&rest[start..i], lexeme.span)?);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if args.len() != arity {
+            let s = if arity == 1 { "" } else { "s" };
+            return Err(self
+                .error(
+                    format!("{name} takes {arity} argument{s}, not {}", args.len()),
+                    span,
+                )
+                .into());
+        }
+        let Some(definitions) = self.macros.get(name).map(|m| m.definitions.clone()) else {
+            return self.standard(name, &args, span);
+        };
+        if self.expanding == MAX_EXPANSION_DEPTH {
+            return Err(self
+                .error(format!("expanding {name} doesn't terminate"), span)
+                .note("it's used with ever larger arguments in its own rules")
+                .into());
+        }
+        let names = args.iter().map(|a| a.name.as_str()).collect::<Vec<_>>();
+        let full_name = format!("{name}({})", names.join(", "));
+        self.generated(full_name, span, |p, s| {
+            let mut rules = Vec::new();
+            p.expanding += 1;
+            for (params, head, body) in definitions {
+                let arguments = params.into_iter().zip(args.iter().cloned()).collect();
+                let outer = std::mem::replace(&mut p.arguments, arguments);
+                let expanded = p.alternatives(s, head, body);
+                p.arguments = outer;
+                rules.extend(expanded?);
+            }
+            p.expanding -= 1;
+            Ok(rules)
+        })
+    }
+
+    /// Reads one argument of an application, `end` being the `,` or `)`
+    /// after it
+    fn argument(&mut self, lexemes: &[Lexeme], end: Span) -> Result<Element> {
+        let Some(first) = lexemes.first() else {
+            return Err(self.error("missing argument", end).into());
+        };
+        if !matches!(
+            first.kind,
+            LexemeKind::Word(_) | LexemeKind::Quoted(_) | LexemeKind::Open
+        ) {
+            return Err(self
+                .error("expected a symbol as argument", first.span)
+                .into());
+        }
+        let mut lexemes = lexemes.iter();
+        let element = self.element(&mut lexemes)?;
+        if let Some(extra) = lexemes.next() {
+            return Err(self
+                .error("expected a single symbol as argument", extra.span)
+                .note("write a sequence as a group, as in `(a b)`")
+                .into());
+        }
+        Ok(element)
+    }
+
+    /// Reads one alternative, up to the `|` or `)` ending it
+    fn alternative(
+        &mut self,
+        symbol: Symbol,
+        head: Span,
+        lexemes: &mut Iter<'_, Lexeme>,
+    ) -> Result<Rule> {
+        let mut rule = Rule::new(symbol, head);
+        let mut last = head;
+        let mut last_sem = false;
+        let mut explicit_precedence = None;
+        while let Some(lexeme) = lexemes.as_slice().first() {
+            match &lexeme.kind {
+                LexemeKind::Pipe | LexemeKind::Close => break,
+                LexemeKind::Directive(directive) if directive == "prec" => {
+                    lexemes.next();
+                    let prec_token = lexemes.next();
+                    let prec = prec_token.and_then(|l| match &l.kind {
+                        LexemeKind::Word(tok) | LexemeKind::Quoted(tok) => {
+                            let t = self.parsed.tokens.iter().position(|t| t == tok)?;
+                            self.parsed.precedences.get(&Token(t)).copied()
+                        }
+                        _ => None,
+                    });
+                    if prec.is_none() {
+                        self.warn(
+                            "%prec needs a token with a declared precedence, ignoring",
+                            prec_token.map_or(lexeme.span, |l| l.span),
+                        );
+                    }
+                    explicit_precedence = prec;
+                    last = prec_token.map_or(lexeme.span, |l| l.span);
+                }
+                LexemeKind::Semantic(sem) => {
+                    if last_sem {
+                        return Err(self
+                            .error("duplicate semantics", lexeme.span)
+                            .note("only one semantic can run between two symbols")
+                            .into());
+                    }
+                    lexemes.next();
+                    let sem = self.semantic(sem);
+                    rule.semantics.push(Some(sem));
+                    rule.semantic_spans.push(Some(lexeme.span));
+                    last_sem = true;
+                    last = lexeme.span;
+                }
+                LexemeKind::ReduceSemantic(sem) => {
+                    lexemes.next();
+                    rule.reduce_sem = Some(self.semantic(sem));
+                    rule.reduce_sem_span = Some(lexeme.span);
+                    last = lexeme.span;
+                }
+                LexemeKind::Word(_) | LexemeKind::Quoted(_) | LexemeKind::Open => {
+                    let element = self.element(lexemes)?;
+                    self.push(&mut rule, element.symbol, element.span, last_sem);
+                    last_sem = false;
+                    last = element.span;
+                }
+                LexemeKind::Question | LexemeKind::Star | LexemeKind::Plus => {
+                    return Err(self
+                        .error("nothing to repeat", lexeme.span)
+                        .note("write it as a quoted literal to use it as a terminal")
+                        .into());
+                }
+                LexemeKind::Comma => {
+                    return Err(self
+                        .error("unexpected `,`", lexeme.span)
+                        .note("write it as ',' to use it as a terminal")
+                        .into());
+                }
+                LexemeKind::Arrow => {
+                    return Err(self
+                        .error("unexpected `->` in a rule body", lexeme.span)
+                        .note("write it as '->' to use it as a terminal")
+                        .note("a rule starts in column 1, after a `;` or with `A ->` on a new line")
+                        .into());
+                }
+                LexemeKind::Directive(directive) => {
+                    return Err(self
+                        .error(format!("unknown directive %{directive}"), lexeme.span)
+                        .note(format!(
+                            "write it as '%{directive}' to use it as a terminal"
+                        ))
+                        .into());
+                }
+                LexemeKind::Semicolon => unreachable!("statements are split on `;`"),
+            }
+        }
+        rule.precedence = explicit_precedence.or(rule.precedence);
+        rule.span = head.to(last);
+        Ok(rule)
+    }
+
+    /// Reads the alternatives of a rule or a group, one [`Rule`] each
+    fn alternatives(&mut self, symbol: Symbol, head: Span, body: &[Lexeme]) -> Result<Vec<Rule>> {
+        let mut lexemes = body.iter();
+        let mut rules = Vec::new();
+        let mut head = head;
+        loop {
+            rules.push(self.alternative(symbol, head, &mut lexemes)?);
+            match lexemes.next() {
+                None => return Ok(rules),
+                Some(l) if l.kind == LexemeKind::Pipe => head = l.span,
+                Some(l) => return Err(self.error("unmatched `)`", l.span).into()),
+            }
+        }
+    }
+}
+
+/// Whether `rest`, at the start of a line, is the head of a rule, `A ->`
+/// or `A(x, y) ->`
+fn starts_rule(rest: &[Lexeme]) -> bool {
+    match rest {
+        [Lexeme {
+            kind: LexemeKind::Word(_),
+            ..
+        }, Lexeme {
+            kind: LexemeKind::Arrow,
+            ..
+        }, ..] => true,
+        [Lexeme {
+            kind: LexemeKind::Word(_),
+            ..
+        }, Lexeme {
+            kind: LexemeKind::Open,
+            ..
+        }, rest @ ..] => group_end(rest).is_some_and(|end| {
+            rest.get(end + 1)
+                .is_some_and(|l| l.kind == LexemeKind::Arrow)
+        }),
+        _ => false,
+    }
+}
+
+/// Splits the lexemes into statements. A statement ends with a `;`, when
+/// a line starts without indentation, so rules and declarations can
+/// continue on indented lines, when a line starts with the head of a
+/// rule, so rules can all be indented, or at the end of a file. A line
+/// starting with `|` always continues the rule before it.
+pub(crate) fn statements(lexemes: &[Lexeme]) -> Vec<&[Lexeme]> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    for (i, lexeme) in lexemes.iter().enumerate() {
+        let (new_line, new_file) = match i.checked_sub(1).map(|i| lexemes[i].span) {
+            Some(last) => (last.line != lexeme.span.line, last.file != lexeme.span.file),
+            None => (false, false),
+        };
+        if lexeme.kind == LexemeKind::Semicolon
+            || (new_line
+                && lexeme.kind != LexemeKind::Pipe
+                && (lexeme.span.column == 1 || starts_rule(&lexemes[i..])))
+            || new_file
+        {
+            statements.push(&lexemes[start..i]);
+            start = i + usize::from(lexeme.kind == LexemeKind::Semicolon);
+        }
+    }
+    statements.push(&lexemes[start..]);
+    statements.retain(|s| !s.is_empty());
+    statements
+}
+
+/// Parses the statements read from `sources`, returning the first error
+/// found. Every alternative becomes its own rule, numbered in the order
+/// they're written, followed by the rules generated for EBNF operators.
+pub(crate) fn parse(sources: &[SourceFile], statements: &[Vec<Lexeme>]) -> Result<Parsed> {
+    let mut parser = Parser {
+        sources,
+        parsed: Parsed::default(),
+        generated: Vec::new(),
+        declared: HashSet::new(),
+        strict: false,
+        precedence_spans: HashMap::new(),
+        start_span: None,
+        defined_at: HashMap::new(),
+        macros: HashMap::new(),
+        arguments: HashMap::new(),
+        expanding: 0,
+    };
+
+    let mut rules = Vec::new();
+    let mut declarations = Vec::new();
+    for statement in statements.iter().filter(|s| !s.is_empty()) {
+        let span = statement[0].span.to(statement[statement.len() - 1].span);
+        if let LexemeKind::Directive(directive) = &statement[0].kind {
+            match directive.as_str() {
+                "left" | "right" | "nonassoc" | "precedence" | "token" | "start" => {
+                    declarations.push((directive.as_str(), span, &statement[1..]));
+                }
+                "strict" => parser.strict = true,
+                _ => parser.warn(format!("unknown directive %{directive}, ignoring"), span),
+            }
+            continue;
+        }
+        let arrow = statement.iter().position(|l| l.kind == LexemeKind::Arrow);
+        let (head, body) = statement.split_at(arrow.unwrap_or(statement.len()));
+        match head {
+            [Lexeme {
+                kind: LexemeKind::Word(symbol),
+                span: head,
+            }] if arrow.is_some() => {
+                parser.define(symbol, *head)?;
+                let symbol = Symbol(index_of(&mut parser.parsed.symbols, symbol));
+                rules.push((symbol, *head, &body[1..]));
+            }
+            [Lexeme {
+                kind: LexemeKind::Word(name),
+                span: head,
+            }, Lexeme {
+                kind: LexemeKind::Open,
+                ..
+            }, params @ .., Lexeme {
+                kind: LexemeKind::Close,
+                ..
+            }] if arrow.is_some() => {
+                parser.define_macro(name, *head, params, &body[1..])?;
+            }
+            _ => parser.warn("expected a rule `A -> ...`, ignoring", span),
+        }
+    }
+    if let Some(name) = parser
+        .parsed
+        .symbols
+        .iter()
+        .find(|s| parser.macros.contains_key(*s))
+    {
+        let head = parser.macros[name].definitions[0].1;
+        return Err(parser
+            .error(
+                format!("{name} is defined both with and without parameters"),
+                head,
+            )
+            .into());
+    }
+    let mut level = 0;
+    for (directive, span, line) in declarations {
+        let associativity = match directive {
+            "left" => Associativity::Left,
+            "right" => Associativity::Right,
+            "nonassoc" => Associativity::NonAssoc,
+            "precedence" => Associativity::Precedence,
+            "token" => {
+                parser.declared_tokens("token", line);
+                continue;
+            }
+            _ => {
+                parser.start(span, line)?;
+                continue;
+            }
+        };
+        for (tok, tok_span) in parser.declared_tokens("precedence", line) {
+            if let Some(&first) = parser.precedence_spans.get(&tok) {
+                return Err(parser
+                    .error(
+                        format!(
+                            "precedence of {} declared twice",
+                            parser.parsed.tokens[tok.0]
+                        ),
+                        tok_span,
+                    )
+                    .note(format!("first declared at {}", parser.position(first)))
+                    .into());
+            }
+            let precedence = Precedence {
+                level,
+                associativity,
+            };
+            parser.parsed.precedences.insert(tok, precedence);
+            parser.precedence_spans.insert(tok, tok_span);
+        }
+        level += 1;
+    }
+    for (symbol, head, body) in rules {
+        let rules = parser.alternatives(symbol, head, body)?;
+        parser.parsed.rules.extend(rules);
+    }
+    parser.parsed.rules.append(&mut parser.generated);
+    if parser.parsed.start.is_none() {
+        // The first file's own rules come after those it includes first
+        let rules = &parser.parsed.rules;
+        let first = rules.iter().find(|r| r.span.file == 0).or(rules.first());
+        parser.parsed.start = first.map(|r| r.symbol);
+    }
+    Ok(parser.parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+    use crate::grammar::Grammar;
+
+    fn grammar(lines: &[&str]) -> Grammar {
+        Grammar::new(lines.iter().map(|l| Cow::Borrowed(*l))).unwrap()
+    }
+
+    /// The message of the error `lines` fail with
+    fn error(lines: &[&str]) -> String {
+        match Grammar::new(lines.iter().map(|l| Cow::Borrowed(*l))) {
+            Ok(_) => panic!("{lines:?} parsed"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    /// The rules written and generated, before the axiom, as text
+    fn rules(g: &Grammar) -> Vec<String> {
+        let rules = &g.get_rules()[..g.axiom().unwrap()];
+        let sem = |s: Option<Semantic>| s.map(|s| format!("{{{}}}", g.get_semantic(s)));
+        rules
+            .iter()
+            .map(|rule| {
+                let mut body = Vec::new();
+                for (i, &symbol) in rule.tokens.iter().enumerate() {
+                    body.extend(sem(rule.semantics.get(i).copied().flatten()));
+                    body.push(g.get_grammar_symbol(symbol).to_string());
+                }
+                body.extend(sem(rule
+                    .semantics
+                    .get(rule.tokens.len())
+                    .copied()
+                    .flatten()));
+                body.extend(
+                    rule.reduce_sem
+                        .map(|s| format!("R{{{}}}", g.get_semantic(s))),
+                );
+                format!("{} -> {}", g.get_symbol(rule.symbol), body.join(" "))
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parameterized_nonterminals() {
+        let g = grammar(&[
+            "delimited(open, X, close) -> open X close",
+            "comma_list(X) -> X | comma_list(X) ',' X",
+            "Call -> id delimited('(', comma_list(Expr), ')')",
+            "Expr -> e",
+        ]);
+        assert!(g.diagnostics().is_empty());
+        assert_eq!(
+            rules(&g),
+            [
+                "Call -> id delimited('(', comma_list(Expr), ')')",
+                "Expr -> e",
+                "comma_list(Expr) -> Expr",
+                "comma_list(Expr) -> comma_list(Expr) , Expr",
+                "delimited('(', comma_list(Expr), ')') -> ( comma_list(Expr) )",
+            ]
+        );
+    }
+
+    #[test]
+    fn parameterized_nonterminal_errors() {
+        assert!(error(&["p(X) -> X", "S -> p(a, b)"]).contains("p takes 1 argument, not 2"));
+        assert!(error(&["list(X) -> X", "S -> list(a)"])
+            .contains("list is a standard parameterized non terminal"));
+        assert!(error(&["f(X) -> X | f(list(X))", "S -> f(a)"])
+            .contains("expanding f doesn't terminate"));
+    }
+
+    fn warnings(g: &Grammar) -> Vec<String> {
+        g.diagnostics().iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn token_declarations() {
+        let g = grammar(&["%token x y S", "S -> a"]);
+        let tokens = g.iter_tokens().map(|t| g.get_token(t)).collect::<Vec<_>>();
+        assert_eq!(tokens, ["x", "y", "a"]);
+        assert!(g.iter_tokens().all(|t| g.get_precedence(t).is_none()));
+        let warnings = warnings(&g);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("token declared for non terminal S, ignoring"));
+    }
+
+    #[test]
+    fn start_declarations() {
+        let g = grammar(&["%start T", "S -> s", "T -> S t", "%start T"]);
+        assert_eq!(g.start().map(|s| g.get_symbol(s)), Some("T"));
+        let warnings = warnings(&g);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("start symbol declared twice, ignoring"));
+
+        let message = error(&["%start T", "%start S", "S -> T", "T -> t"]);
+        assert!(message.contains("start symbol S conflicts with T"));
+        assert!(error(&["%start U", "S -> s"]).contains("start symbol U has no rules"));
+    }
+
+    #[test]
+    fn strict_terminals() {
+        let g = grammar(&["%strict", "%token a", "%left '+'", "S -> a '+' a '-'"]);
+        assert_eq!(g.get_rules().len(), 2);
+        let message = error(&["%strict", "%token a", "S -> a b"]);
+        assert!(message.contains("undeclared terminal b"));
+        // Without %strict a misspelt symbol is a terminal
+        assert_eq!(grammar(&["S -> a b"]).iter_tokens().count(), 2);
+    }
+
+    #[test]
+    fn operators() {
+        let g = grammar(&["S -> Item* ';' | (a b)+ | x? | Item % ','", "Item -> i"]);
+        assert!(g.diagnostics().is_empty());
+        assert_eq!(
+            rules(&g),
+            [
+                "S -> list(Item) ;",
+                "S -> nonempty_list((a b))",
+                "S -> option(x)",
+                "S -> separated_nonempty_list(',', Item)",
+                "Item -> i",
+                "list(Item) ->",
+                "list(Item) -> list(Item) Item",
+                "(a b) -> a b",
+                "nonempty_list((a b)) -> (a b)",
+                "nonempty_list((a b)) -> nonempty_list((a b)) (a b)",
+                "option(x) ->",
+                "option(x) -> x",
+                "separated_nonempty_list(',', Item) -> Item",
+                "separated_nonempty_list(',', Item) -> separated_nonempty_list(',', Item) , Item",
+            ]
+        );
+    }
+
+    #[test]
+    fn operators_share_symbols_and_keep_semantics_in_place() {
+        let g = grammar(&[
+            "S -> Item* Item* | ({g} a R{r} | b)? {after} c*",
+            "Item -> {pre} i",
+        ]);
+        assert!(g.diagnostics().is_empty());
+        assert_eq!(
+            rules(&g),
+            [
+                "S -> list(Item) list(Item)",
+                "S -> option(({g} a R{r} | b)) {after} list(c)",
+                "Item -> {pre} i",
+                "list(Item) ->",
+                "list(Item) -> list(Item) Item",
+                "({g} a R{r} | b) -> {g} a R{r}",
+                "({g} a R{r} | b) -> b",
+                "option(({g} a R{r} | b)) ->",
+                "option(({g} a R{r} | b)) -> ({g} a R{r} | b)",
+                "list(c) ->",
+                "list(c) -> list(c) c",
+            ]
+        );
+    }
+
+    #[test]
+    fn standard_nonterminals() {
+        let g = grammar(&["S -> separated_list(',', x) {s} Item+ R{done}", "Item -> i"]);
+        assert!(g.diagnostics().is_empty());
+        assert_eq!(
+            rules(&g),
+            [
+                "S -> separated_list(',', x) {s} nonempty_list(Item) R{done}",
+                "Item -> i",
+                "separated_nonempty_list(',', x) -> x",
+                "separated_nonempty_list(',', x) -> separated_nonempty_list(',', x) , x",
+                "separated_list(',', x) ->",
+                "separated_list(',', x) -> separated_nonempty_list(',', x)",
+                "nonempty_list(Item) -> Item",
+                "nonempty_list(Item) -> nonempty_list(Item) Item",
+            ]
+        );
+    }
+
+    #[test]
+    fn semicolons_in_semantics() {
+        let g = grammar(&["S -> a {x; y} b R{done; now}", "S -> c"]);
+        assert!(g.diagnostics().is_empty());
+        let rule = &g.get_rules()[0];
+        assert_eq!(rule.tokens.len(), 2);
+        let sem = rule.semantics[1].unwrap();
+        assert_eq!(g.get_semantic(sem), "x; y");
+        assert_eq!(g.get_semantic(rule.reduce_sem.unwrap()), "done; now");
+        assert_eq!(g.get_rules().len(), 3);
+    }
+
+    #[test]
+    fn alternatives_on_their_own_lines() {
+        let g = grammar(&["E -> E '+' T", "| T", "T -> id", "  | '(' E ')'"]);
+        assert!(g.diagnostics().is_empty());
+        let heads: Vec<_> = g
+            .get_rules()
+            .iter()
+            .map(|r| g.get_symbol(r.symbol))
+            .collect();
+        assert_eq!(heads, ["E", "E", "T", "T", "E'"]);
+    }
+
+    #[test]
+    fn indented_rules() {
+        let g = grammar(&[
+            "  S -> a L(T)",
+            "  S -> b",
+            "    | c",
+            "  T -> d",
+            "  L(x) -> x",
+        ]);
+        assert!(g.diagnostics().is_empty());
+        let heads: Vec<_> = g
+            .get_rules()
+            .iter()
+            .map(|r| g.get_symbol(r.symbol))
+            .collect();
+        assert_eq!(heads, ["S", "S", "S", "T", "L(T)", "S'"]);
+    }
+}
@@ -0,0 +1,753 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use tinytemplate::{format_unescaped, TinyTemplate};
+use typed_arena::Arena;
+
+use crate::{
+    closures::Automata,
+    config::{self, Config, SemanticTemplateSource, SingleOrMultiLineString, TemplateSource},
+    context::{
+        AcceptContext, ActionContext, ActionsContext, DescentAlternativeContext,
+        DescentFunctionContext, DescentNonterminalContext, DescentTerminalContext, GotoContext,
+        PredictContext, ReduceContext, SemBodyContext, SemContext, SemReduceCaseContext,
+        SemStateCaseContext, ShiftContext,
+    },
+    error::{Error, Result},
+    export::identifiers,
+    grammar::{Grammar, GrammarSymbol, Semantic, Token},
+    ll::PredictTable,
+};
+
+fn format_token(token: Token, grammar: &Grammar, replacements: &HashMap<String, String>) -> String {
+    let g = grammar.get_token(token);
+    replacements.get(g).cloned().unwrap_or_else(|| {
+        eprintln!("WARNING: {g} not replaced");
+        g.to_string()
+    })
+}
+
+fn format_token_maybe(
+    token: Option<Token>,
+    grammar: &Grammar,
+    replacements: &HashMap<String, String>,
+) -> String {
+    let g = token.map_or("$", |token| grammar.get_token(token));
+    replacements.get(g).cloned().unwrap_or_else(|| {
+        eprintln!("WARNING: {g} not replaced");
+        g.to_string()
+    })
+}
+
+/// Escapes a name for a quoted Dot label, where instantiated names like
+/// `delimited('"', X, '"')` may hold quotes
+fn dot_label(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A semantic with its body from the replacements, empty without one
+fn sem_body<'a>(
+    grammar: &'a Grammar,
+    replacements: &HashMap<String, SingleOrMultiLineString>,
+    sem: Semantic,
+) -> SemBodyContext<'a> {
+    let semantic_name = grammar.get_semantic(sem);
+    let body = replacements
+        .get(semantic_name)
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    SemBodyContext {
+        semantic: sem,
+        semantic_name,
+        semantic_body: body,
+    }
+}
+
+fn render_template<C: serde::Serialize>(
+    tt: &TinyTemplate,
+    name: &str,
+    context: &C,
+) -> Result<String> {
+    tt.render(name, context)
+        .map_err(|e| Error::template(name, e))
+}
+
+/// Renders a function per symbol through the `descent/*` templates, each
+/// taking the alternatives `predict` has lookaheads for
+fn render_descent(
+    tt: &TinyTemplate,
+    grammar: &Grammar,
+    predict: &PredictTable,
+    token_replace: &HashMap<String, String>,
+    replacements: &HashMap<String, SingleOrMultiLineString>,
+) -> Result<Vec<String>> {
+    let names = grammar.iter_symbols().map(|s| grammar.get_symbol(s));
+    let idents = identifiers(names, &mut HashSet::new(), |ident| ident);
+    let axiom = grammar.axiom().map(|r| grammar.get_rules()[r].symbol);
+    let semantic = |sem| {
+        render_template(
+            tt,
+            "descent/semantic",
+            &sem_body(grammar, replacements, sem),
+        )
+    };
+    let mut functions = Vec::new();
+    for symbol in grammar.iter_symbols().filter(|&s| Some(s) != axiom) {
+        let symbol_not_replaced = grammar.get_symbol(symbol);
+        let symbol_ident = idents[symbol.index()].as_str();
+        let mut alternatives = Vec::new();
+        for (ruleno, rule) in grammar.get_rules().iter().enumerate() {
+            let tokens = predict
+                .iter()
+                .filter(|&(s, _, r)| s == symbol && r == ruleno)
+                .map(|(_, t, _)| format_token_maybe(t, grammar, token_replace))
+                .collect::<Vec<_>>();
+            // Rules of other symbols, or left out by an LL(1) conflict
+            if tokens.is_empty() {
+                continue;
+            }
+            let mut body = Vec::new();
+            for (i, &s) in rule.tokens.iter().enumerate() {
+                if let Some(sem) = rule.semantics.get(i).copied().flatten() {
+                    body.push(semantic(sem)?);
+                }
+                body.push(match s {
+                    GrammarSymbol::Token(t) => render_template(
+                        tt,
+                        "descent/terminal",
+                        &DescentTerminalContext {
+                            token: &format_token(t, grammar, token_replace),
+                            token_not_replaced: grammar.get_token(t),
+                        },
+                    )?,
+                    GrammarSymbol::Symbol(s) => render_template(
+                        tt,
+                        "descent/nonterminal",
+                        &DescentNonterminalContext {
+                            symbol_no: s,
+                            symbol_not_replaced: grammar.get_symbol(s),
+                            symbol_ident: &idents[s.index()],
+                        },
+                    )?,
+                });
+            }
+            let last = rule.semantics.get(rule.tokens.len()).copied().flatten();
+            for sem in last.into_iter().chain(rule.reduce_sem) {
+                body.push(semantic(sem)?);
+            }
+            alternatives.push(render_template(
+                tt,
+                "descent/alternative",
+                &DescentAlternativeContext {
+                    symbol_no: symbol,
+                    symbol_not_replaced,
+                    symbol_ident,
+                    ruleno,
+                    index: alternatives.len(),
+                    tokens,
+                    body: body.join("\n"),
+                },
+            )?);
+        }
+        if alternatives.is_empty() {
+            continue;
+        }
+        let expected = predict
+            .iter()
+            .filter(|&(s, ..)| s == symbol)
+            .map(|(_, t, _)| format_token_maybe(t, grammar, token_replace))
+            .collect();
+        functions.push(render_template(
+            tt,
+            "descent/function",
+            &DescentFunctionContext {
+                symbol_no: symbol,
+                symbol_not_replaced,
+                symbol_ident,
+                expected,
+                body: alternatives.join("\n"),
+            },
+        )?);
+    }
+    Ok(functions)
+}
+
+enum SemanticTemplateGen<'a> {
+    Switch { case: &'a str, body: &'a str },
+    Line { line: &'a str },
+}
+
+impl<'a> SemanticTemplateGen<'a> {
+    fn render<
+        'b,
+        T,
+        C: serde::Serialize,
+        F: Fn(&T) -> C,
+        B: Fn(Semantic) -> SemBodyContext<'b>,
+        R: FnMut(String) -> Result<()>,
+    >(
+        &self,
+        tt: &TinyTemplate,
+        sem: Semantic,
+        data: &[T],
+        case: F,
+        body: B,
+        re: &mut R,
+    ) -> Result<()> {
+        match self {
+            SemanticTemplateGen::Switch { case: c, body: b } => {
+                for s in data {
+                    let s = case(s);
+                    re(tt.render(c, &s).map_err(|e| Error::template(*c, e))?)?;
+                }
+                let s = body(sem);
+                re(tt.render(b, &s).map_err(|e| Error::template(*b, e))?)?;
+            }
+            SemanticTemplateGen::Line { line } => {
+                for s in data {
+                    let s = SemContext {
+                        case: case(s),
+                        body: body(sem),
+                    };
+
+                    re(tt.render(line, &s).map_err(|e| Error::template(*line, e))?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn add_templates<'b, S: Display>(
+    tt: &mut TinyTemplate<'b>,
+    heap: &'b Arena<String>,
+    name: S,
+    template: SemanticTemplateSource,
+) -> Result<SemanticTemplateGen<'b>> {
+    let mut add = |name: String, text: String| {
+        let name = heap.alloc(name).as_str();
+        let text = heap.alloc(text).as_str();
+        tt.add_template(name, text)
+            .map_err(|e| Error::template(name, e))?;
+        Ok::<_, Error>(name)
+    };
+    Ok(match template {
+        SemanticTemplateSource::Switch {
+            case: case_t,
+            body: body_t,
+        } => SemanticTemplateGen::Switch {
+            case: add(format!("{}/case", name), case_t.load_string()?)?,
+            body: add(format!("{}/body", name), body_t.load_string()?)?,
+        },
+        SemanticTemplateSource::Line { line } => SemanticTemplateGen::Line {
+            line: add(format!("{}/line", name), line.load_string()?)?,
+        },
+    })
+}
+
+/// The result files, each written to by the kinds of output it asked for
+struct Sinks {
+    files: Vec<(PathBuf, File)>,
+}
+
+impl Sinks {
+    fn write<D: Display>(&mut self, sinks: &[usize], line: D) -> Result<()> {
+        for &sink in sinks {
+            let (path, file) = &mut self.files[sink];
+            writeln!(file, "{line}").map_err(|e| Error::io(path.as_path(), e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the tables of `automata` and `predict` through the templates in
+/// `config`, writing every result file relative to `base`
+pub fn render(
+    config: Config,
+    base: &Path,
+    grammar: &Grammar,
+    automata: &Automata,
+    predict: &PredictTable,
+) -> Result<()> {
+    let arena = Arena::new();
+    let mut tt = TinyTemplate::new();
+    tt.set_default_formatter(&format_unescaped);
+    let reduce_template = config.reduce_template.load_string()?;
+    tt.add_template("reduce", &reduce_template)
+        .map_err(|e| Error::template("reduce", e))?;
+    let shift_template = config.shift_template.load_string()?;
+    tt.add_template("shift", &shift_template)
+        .map_err(|e| Error::template("shift", e))?;
+    let goto_template = config.goto_template.load_string()?;
+    tt.add_template("goto", &goto_template)
+        .map_err(|e| Error::template("goto", e))?;
+    let accept_template = config
+        .accept_template
+        .map(TemplateSource::load_string)
+        .transpose()?;
+    if let Some(accept_template) = &accept_template {
+        tt.add_template("accept", accept_template)
+            .map_err(|e| Error::template("accept", e))?;
+    }
+    let predict_template = config
+        .predict_template
+        .map(TemplateSource::load_string)
+        .transpose()?;
+    if let Some(predict_template) = &predict_template {
+        tt.add_template("predict", predict_template)
+            .map_err(|e| Error::template("predict", e))?;
+    }
+    let actions_template = config
+        .actions_template
+        .map(TemplateSource::load_string)
+        .transpose()?;
+    if let Some(actions_template) = &actions_template {
+        tt.add_template("actions", actions_template)
+            .map_err(|e| Error::template("actions", e))?;
+    }
+    let descent_templates = config
+        .descent
+        .map(|descent| {
+            Ok::<_, Error>([
+                ("descent/function", descent.function.load_string()?),
+                ("descent/alternative", descent.alternative.load_string()?),
+                ("descent/terminal", descent.terminal.load_string()?),
+                ("descent/nonterminal", descent.nonterminal.load_string()?),
+                ("descent/semantic", descent.semantic.load_string()?),
+            ])
+        })
+        .transpose()?;
+    for (name, template) in descent_templates.iter().flatten() {
+        tt.add_template(name, template)
+            .map_err(|e| Error::template(*name, e))?;
+    }
+    let sem_state = add_templates(
+        &mut tt,
+        &arena,
+        "semantic/state",
+        config.semantics.state_template,
+    )?;
+
+    let sem_reduce = add_templates(
+        &mut tt,
+        &arena,
+        "semantic/reduce",
+        config.semantics.reduce_template,
+    )?;
+
+    let mut sinks = Sinks { files: Vec::new() };
+    let mut shift_sinks = Vec::new();
+    let mut reduce_sinks = Vec::new();
+    let mut goto_sinks = Vec::new();
+    let mut accept_sinks = Vec::new();
+    let mut predict_sinks = Vec::new();
+    let mut actions_sinks = Vec::new();
+    let mut descent_sinks = Vec::new();
+    let mut sem_state_sinks = Vec::new();
+    let mut sem_reduce_sinks = Vec::new();
+    let mut dot_sinks = Vec::new();
+
+    for (path, rules) in config.results {
+        if rules.contains(&config::PrintOption::Accept) && accept_template.is_none() {
+            return Err(Error::MissingTemplate {
+                path,
+                kind: "accept",
+                template: "accept_template",
+            });
+        }
+        if rules.contains(&config::PrintOption::Predict) && predict_template.is_none() {
+            return Err(Error::MissingTemplate {
+                path,
+                kind: "predict",
+                template: "predict_template",
+            });
+        }
+        if rules.contains(&config::PrintOption::Actions) && actions_template.is_none() {
+            return Err(Error::MissingTemplate {
+                path,
+                kind: "actions",
+                template: "actions_template",
+            });
+        }
+        if rules.contains(&config::PrintOption::Descent) && descent_templates.is_none() {
+            return Err(Error::MissingTemplate {
+                path,
+                kind: "descent",
+                template: "descent",
+            });
+        }
+        let sinkno = sinks.files.len();
+        let path = base.join(path);
+        let file = File::create(&path).map_err(|e| Error::io(&path, e))?;
+        sinks.files.push((path, file));
+        if rules.contains(&config::PrintOption::Shift) {
+            shift_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::Reduce) {
+            reduce_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::Goto) {
+            goto_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::Accept) {
+            accept_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::Predict) {
+            predict_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::Actions) {
+            actions_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::Descent) {
+            descent_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::StateSemantics) {
+            sem_state_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::ReduceSemantics) {
+            sem_reduce_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::Dot) {
+            dot_sinks.push(sinkno);
+        }
+        if rules.contains(&config::PrintOption::Bison) {
+            sinks.write(&[sinkno], grammar.to_bison())?;
+        }
+        if rules.contains(&config::PrintOption::Antlr) {
+            let name = sinks.files[sinkno].0.file_stem().unwrap_or_default();
+            let name = name.to_string_lossy().into_owned();
+            sinks.write(&[sinkno], grammar.to_antlr(&name))?;
+        }
+        if rules.contains(&config::PrintOption::Grammar) {
+            sinks.write(&[sinkno], grammar.to_source())?;
+        }
+    }
+
+    sinks.write(&dot_sinks, "digraph automata {")?;
+    sinks.write(&dot_sinks, "\taccept [shape=doublecircle];")?;
+    for (closure, state) in automata.iter_all() {
+        let string = closure
+            .ref_iter()
+            .map(|item| dot_label(&item.to_string(grammar)))
+            .collect::<Vec<_>>()
+            .join("\\n");
+        let sem_state_action = automata
+            .get_state_sem(state.state)
+            .map_or("", |s| grammar.get_semantic(s));
+        let mut sem_reduce = String::new();
+        state.reduce_actions.iter().for_each(|(_, r)| {
+            if let Some(s) = automata.get_reduce_sem(*r) {
+                sem_reduce += &format!("R{r}{{{}}}", grammar.get_semantic(s));
+            }
+        });
+        sinks.write(
+            &dot_sinks,
+            format_args!(
+                "\ti{} [label=\"i{0}\\n{}\\n\\n{{{sem_state_action}}}{sem_reduce}\"];",
+                state.state, string
+            ),
+        )?;
+    }
+
+    for state in automata.iter() {
+        for (&token, &next) in state.shift_actions.iter() {
+            let formatted = tt
+                .render(
+                    "shift",
+                    &ShiftContext {
+                        state: state.state,
+                        token: &format_token(token, grammar, &config.token_replace),
+                        next,
+                    },
+                )
+                .map_err(|e| Error::template("shift", e))?;
+            sinks.write(&shift_sinks, formatted)?;
+            sinks.write(
+                &dot_sinks,
+                format_args!(
+                    "\ti{} -> i{} [label=\"{}\"];",
+                    state.state,
+                    next,
+                    dot_label(grammar.get_token(token))
+                ),
+            )?;
+        }
+        for (&token, &ruleno) in state.reduce_actions.iter() {
+            let rule = &grammar.get_rules()[ruleno];
+            let symbol_not_replaced = grammar.get_symbol(rule.symbol);
+            let formatted = tt
+                .render(
+                    "reduce",
+                    &ReduceContext {
+                        state: state.state,
+                        token: &format_token_maybe(token, grammar, &config.token_replace),
+                        ruleno,
+                        elements: rule.tokens.len(),
+                        symbol_not_replaced,
+                        symbol_no: rule.symbol,
+                    },
+                )
+                .map_err(|e| Error::template("reduce", e))?;
+            sinks.write(&reduce_sinks, formatted)?;
+        }
+        if state.accept {
+            if !accept_sinks.is_empty() {
+                let formatted = tt
+                    .render(
+                        "accept",
+                        &AcceptContext {
+                            state: state.state,
+                            token: &format_token_maybe(None, grammar, &config.token_replace),
+                        },
+                    )
+                    .map_err(|e| Error::template("accept", e))?;
+                sinks.write(&accept_sinks, formatted)?;
+            }
+            sinks.write(
+                &dot_sinks,
+                format_args!("\ti{} -> accept [label=\"$\"];", state.state),
+            )?;
+        }
+        for (&symbol, &next) in state.goto_actions.iter() {
+            let symbol_not_replaced = grammar.get_symbol(symbol);
+            let formatted = tt
+                .render(
+                    "goto",
+                    &GotoContext {
+                        state: state.state,
+                        symbol_not_replaced,
+                        symbol_no: symbol,
+                        next,
+                    },
+                )
+                .map_err(|e| Error::template("goto", e))?;
+            sinks.write(&goto_sinks, formatted)?;
+
+            sinks.write(
+                &dot_sinks,
+                format_args!(
+                    "\ti{} -> i{} [label=\"{}\"];",
+                    state.state,
+                    next,
+                    dot_label(grammar.get_symbol(symbol))
+                ),
+            )?;
+        }
+    }
+
+    if !actions_sinks.is_empty() {
+        for state in automata.iter() {
+            let mut actions = BTreeMap::<_, Vec<_>>::new();
+            for (&token, &next) in &state.shift_actions {
+                actions.entry(Some(token)).or_default().push(ActionContext {
+                    shift: true,
+                    next: Some(next),
+                    ..Default::default()
+                });
+            }
+            if state.accept {
+                actions.entry(None).or_default().push(ActionContext {
+                    accept: true,
+                    ..Default::default()
+                });
+            }
+            for (&token, rules) in &state.glr_reduce_actions {
+                for &ruleno in rules {
+                    let rule = &grammar.get_rules()[ruleno];
+                    actions.entry(token).or_default().push(ActionContext {
+                        reduce: true,
+                        ruleno: Some(ruleno),
+                        elements: Some(rule.tokens.len()),
+                        symbol_no: Some(rule.symbol),
+                        symbol_not_replaced: Some(grammar.get_symbol(rule.symbol)),
+                        ..Default::default()
+                    });
+                }
+            }
+            for (token, actions) in actions {
+                let formatted = tt
+                    .render(
+                        "actions",
+                        &ActionsContext {
+                            state: state.state,
+                            token: &format_token_maybe(token, grammar, &config.token_replace),
+                            actions,
+                        },
+                    )
+                    .map_err(|e| Error::template("actions", e))?;
+                sinks.write(&actions_sinks, formatted)?;
+            }
+        }
+    }
+
+    if !predict_sinks.is_empty() {
+        for (symbol, token, ruleno) in predict.iter() {
+            let rule = &grammar.get_rules()[ruleno];
+            let rhs = rule.tokens.iter().map(|&s| grammar.get_grammar_symbol(s));
+            let formatted = tt
+                .render(
+                    "predict",
+                    &PredictContext {
+                        symbol_no: symbol,
+                        symbol_not_replaced: grammar.get_symbol(symbol),
+                        token: &format_token_maybe(token, grammar, &config.token_replace),
+                        ruleno,
+                        elements: rule.tokens.len(),
+                        rhs: rhs.collect(),
+                    },
+                )
+                .map_err(|e| Error::template("predict", e))?;
+            sinks.write(&predict_sinks, formatted)?;
+        }
+    }
+
+    if !descent_sinks.is_empty() {
+        let functions = render_descent(
+            &tt,
+            grammar,
+            predict,
+            &config.token_replace,
+            &config.semantics.replacements,
+        )?;
+        for function in functions {
+            sinks.write(&descent_sinks, function)?;
+        }
+    }
+
+    let mut render = |formatted: String| sinks.write(&sem_state_sinks, formatted);
+    for (sem, states) in
+        automata
+            .iter_state_sem()
+            .fold(BTreeMap::<_, Vec<_>>::new(), |mut hm, (ruleno, sem)| {
+                hm.entry(sem).or_default().push(ruleno);
+                hm
+            })
+    {
+        sem_state.render(
+            &tt,
+            sem,
+            &states,
+            |&state| SemStateCaseContext { state },
+            |sem| sem_body(grammar, &config.semantics.replacements, sem),
+            &mut render,
+        )?;
+    }
+
+    let mut render = |formatted: String| sinks.write(&sem_reduce_sinks, formatted);
+    for (sem, rules) in
+        automata
+            .iter_reduce_sem()
+            .fold(BTreeMap::<_, Vec<_>>::new(), |mut hm, (ruleno, sem)| {
+                hm.entry(sem).or_default().push(ruleno);
+                hm
+            })
+    {
+        sem_reduce.render(
+            &tt,
+            sem,
+            &rules,
+            |&ruleno| SemReduceCaseContext { ruleno },
+            |sem| sem_body(grammar, &config.semantics.replacements, sem),
+            &mut render,
+        )?;
+    }
+
+    sinks.write(&dot_sinks, "}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    #[test]
+    fn descent_functions() {
+        let grammar = Grammar::new(
+            [
+                "E -> T E' R{expr}",
+                "E' -> '+' T {add} E' |",
+                "T -> id | id '[' E ']' | '(' E ')'",
+            ]
+            .into_iter()
+            .map(Cow::Borrowed),
+        )
+        .unwrap();
+        let predict = PredictTable::new(&grammar);
+        let mut tt = TinyTemplate::new();
+        tt.set_default_formatter(&format_unescaped);
+        for (name, text) in [
+            (
+                "descent/function",
+                "fn {symbol_ident} ({symbol_not_replaced} #{symbol_no}) on{{ for t in expected }} {t}{{ endfor }}\n{body}",
+            ),
+            (
+                "descent/alternative",
+                "  {index}: rule {ruleno} of {symbol_ident} on{{ for t in tokens }} {t}{{ endfor }}\n{body}",
+            ),
+            ("descent/terminal", "    eat {token} ({token_not_replaced})"),
+            (
+                "descent/nonterminal",
+                "    call {symbol_ident} ({symbol_not_replaced} #{symbol_no})",
+            ),
+            ("descent/semantic", "    {semantic_name}: {semantic_body}"),
+        ] {
+            tt.add_template(name, text).unwrap();
+        }
+        let token_replace = [
+            ("id", "ID"),
+            ("+", "PLUS"),
+            ("[", "LBRACKET"),
+            ("]", "RBRACKET"),
+            ("(", "LPAREN"),
+            (")", "RPAREN"),
+            ("$", "EOF"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let replacements = [("expr", "return e;"), ("add", "e += t;")]
+            .into_iter()
+            .map(|(k, v)| {
+                (
+                    k.to_string(),
+                    SingleOrMultiLineString::Single(v.to_string()),
+                )
+            })
+            .collect();
+        let functions =
+            render_descent(&tt, &grammar, &predict, &token_replace, &replacements).unwrap();
+        // `T -> id '[' E ']'` loses `id` to `T -> id` and gets no alternative
+        assert_eq!(predict.conflicts()[0].rules, [3, 4]);
+        assert_eq!(
+            functions,
+            [
+                "fn E (E #0) on ID LPAREN
+  0: rule 0 of E on ID LPAREN
+    call T (T #2)
+    call E_ (E' #1)
+    expr: return e;",
+                "fn E_ (E' #1) on EOF PLUS RBRACKET RPAREN
+  0: rule 1 of E_ on PLUS
+    eat PLUS (+)
+    call T (T #2)
+    add: e += t;
+    call E_ (E' #1)
+  1: rule 2 of E_ on EOF RBRACKET RPAREN
+",
+                "fn T (T #2) on ID LPAREN
+  0: rule 3 of T on ID
+    eat ID (id)
+  1: rule 5 of T on LPAREN
+    eat LPAREN (()
+    call E (E #0)
+    eat RPAREN ())",
+            ]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::grammar::Token;
+
+/// A dense set of tokens, plus the end of input (`None`), one bit each.
+///
+/// Trailing empty words are never stored, so sets with the same elements
+/// compare and hash the same regardless of how they were built.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TokenSet {
+    words: Vec<u64>,
+}
+
+const fn bit(token: Option<Token>) -> (usize, u64) {
+    let index = match token {
+        None => 0,
+        Some(t) => t.index() + 1,
+    };
+    (index / 64, 1 << (index % 64))
+}
+
+impl TokenSet {
+    pub const fn new() -> Self {
+        Self { words: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn contains(&self, token: Option<Token>) -> bool {
+        let (word, mask) = bit(token);
+        self.words.get(word).is_some_and(|w| w & mask != 0)
+    }
+
+    /// Adds a token, returning whether it was new
+    pub fn insert(&mut self, token: Option<Token>) -> bool {
+        let (word, mask) = bit(token);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let new = self.words[word] & mask == 0;
+        self.words[word] |= mask;
+        new
+    }
+
+    /// Adds every token of `other`, returning whether the set grew
+    pub fn union_with(&mut self, other: &Self) -> bool {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        let mut changed = false;
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            changed |= *o & !*w != 0;
+            *w |= o;
+        }
+        changed
+    }
+
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.words
+            .iter()
+            .enumerate()
+            .all(|(i, w)| w & !other.words.get(i).copied().unwrap_or(0) == 0)
+    }
+
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        self.words.iter().zip(&other.words).all(|(w, o)| w & o == 0)
+    }
+
+    /// The end of input first, then the tokens in index order
+    pub fn iter(&self) -> impl Iterator<Item = Option<Token>> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &w)| {
+            (0..64)
+                .filter(move |b| w & (1 << b) != 0)
+                .map(move |b| match i * 64 + b {
+                    0 => None,
+                    index => Some(Token::from_index(index - 1)),
+                })
+        })
+    }
+}
+
+impl Extend<Option<Token>> for TokenSet {
+    fn extend<T: IntoIterator<Item = Option<Token>>>(&mut self, iter: T) {
+        for token in iter {
+            self.insert(token);
+        }
+    }
+}
+
+impl FromIterator<Option<Token>> for TokenSet {
+    fn from_iter<T: IntoIterator<Item = Option<Token>>>(iter: T) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
@@ -0,0 +1,445 @@
+//! Rewrites a grammar for top-down parsing, removing left recursion and
+//! left-factoring alternatives that start the same way.
+//!
+//! Helper symbols are named after the symbol they're split from, `E_tail`
+//! for the repetition left recursion becomes and `E_rest` for what follows
+//! a common prefix, numbered when the name is taken. Semantics keep the
+//! order they run in: when a rule no longer ends where its symbol is
+//! complete, its reduce semantic runs as a semantic right before the helper
+//! symbol instead. A semantic with nowhere to go, because it runs before a
+//! left recursive symbol or would share a gap between two symbols with
+//! another, is dropped with a warning.
+
+use std::collections::HashSet;
+
+use crate::{
+    analysis::Analysis,
+    diagnostic::{Diagnostic, Span},
+    grammar::{Grammar, GrammarSymbol, Rule, Semantic, Symbol},
+};
+
+/// Makes room in `rule` for a semantic before its next symbol
+fn open_gap(rule: &mut Rule) {
+    let gap = rule.tokens.len();
+    if rule.semantics.len() <= gap {
+        rule.semantics.resize(gap + 1, None);
+        rule.semantic_spans.resize(gap + 1, None);
+    }
+}
+
+fn push(rule: &mut Rule, symbol: GrammarSymbol, span: Span) {
+    open_gap(rule);
+    rule.tokens.push(symbol);
+    rule.token_spans.push(span);
+}
+
+/// The semantic before symbol `i` of `rule`, or after the last one
+fn semantic_at(rule: &Rule, i: usize) -> Option<(Semantic, Option<Span>)> {
+    let sem = rule.semantics.get(i).copied().flatten()?;
+    Some((sem, rule.semantic_spans.get(i).copied().flatten()))
+}
+
+fn reduce_semantic(rule: &Rule) -> Option<(Semantic, Option<Span>)> {
+    Some((rule.reduce_sem?, rule.reduce_sem_span))
+}
+
+fn dropped(grammar: &Grammar, sem: Semantic, span: Span) -> Diagnostic {
+    Diagnostic::warning(format!("dropping semantic {}", grammar.get_semantic(sem)))
+        .at(grammar.locate(span))
+}
+
+/// Runs `sem` at the end of `rule`, unless another semantic already does
+fn place(
+    grammar: &Grammar,
+    rule: &mut Rule,
+    (sem, span): (Semantic, Option<Span>),
+    warnings: &mut Vec<Diagnostic>,
+) {
+    open_gap(rule);
+    let gap = rule.tokens.len();
+    match rule.semantics[gap] {
+        Some(kept) => warnings.push(dropped(grammar, sem, span.unwrap_or(rule.span)).note(
+            format!(
+                "it would run where {{{}}} does, and only one semantic can run between two symbols",
+                grammar.get_semantic(kept)
+            ),
+        )),
+        None => {
+            rule.semantics[gap] = Some(sem);
+            rule.semantic_spans[gap] = span;
+        }
+    }
+}
+
+/// Appends the symbols of `from` from `skip` on, with the semantics before
+/// each and after the last
+fn append(
+    grammar: &Grammar,
+    to: &mut Rule,
+    from: &Rule,
+    skip: usize,
+    warnings: &mut Vec<Diagnostic>,
+) {
+    for i in skip..from.tokens.len() {
+        if let Some(sem) = semantic_at(from, i) {
+            place(grammar, to, sem, warnings);
+        }
+        push(to, from.tokens[i], from.token_spans[i]);
+    }
+    if let Some(sem) = semantic_at(from, from.tokens.len()) {
+        place(grammar, to, sem, warnings);
+    }
+}
+
+/// Puts `new` in place of the rules of `symbol`, where the first of them was
+fn replace(rules: &mut Vec<Rule>, symbol: Symbol, new: Vec<Rule>) {
+    let at = rules.iter().position(|r| r.symbol == symbol);
+    rules.retain(|r| r.symbol != symbol);
+    let at = at.unwrap_or(rules.len());
+    rules.splice(at..at, new);
+}
+
+/// The symbols in the order their first rule comes
+fn symbols_in_order(rules: &[Rule]) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    for rule in rules {
+        if !symbols.contains(&rule.symbol) {
+            symbols.push(rule.symbol);
+        }
+    }
+    symbols
+}
+
+/// The symbols `symbol` derives sentential forms starting with, also
+/// looking past symbols that derive the empty string if `grammar` is given
+fn left_corners(rules: &[Rule], symbol: Symbol, grammar: Option<&Grammar>) -> HashSet<Symbol> {
+    let mut corners = HashSet::new();
+    let mut stack = vec![symbol];
+    while let Some(s) = stack.pop() {
+        for rule in rules.iter().filter(|r| r.symbol == s) {
+            for &first in &rule.tokens {
+                let GrammarSymbol::Symbol(first) = first else {
+                    break;
+                };
+                if corners.insert(first) {
+                    stack.push(first);
+                }
+                if !grammar.is_some_and(|g| g.nullable(first)) {
+                    break;
+                }
+            }
+        }
+    }
+    corners
+}
+
+impl Grammar {
+    /// Removes left recursion, direct or through other symbols. Symbols
+    /// are taken in the order of their rules, and a rule `A -> B γ` with
+    /// `B` taken before `A` and deriving something that starts with `A` is
+    /// replaced by `A -> δ γ` for each `B -> δ`. That leaves direct left
+    /// recursion, where `A -> A α | β` becomes `A -> β A_tail` and
+    /// `A_tail -> α A_tail | ε`, and cycles `A -> A`, which are dropped.
+    /// Left recursion behind symbols deriving the empty string is only
+    /// reported.
+    pub fn remove_left_recursion(&mut self) -> Vec<Diagnostic> {
+        let mut warnings = Vec::new();
+        let mut rules = self.written_rules().to_vec();
+        let order = symbols_in_order(&rules);
+        for (i, &a) in order.iter().enumerate() {
+            for &b in &order[..i] {
+                if !left_corners(&rules, b, None).contains(&a) {
+                    continue;
+                }
+                let mut substituted = Vec::new();
+                for rule in rules.iter().filter(|r| r.symbol == a) {
+                    if rule.tokens.first() != Some(&GrammarSymbol::Symbol(b)) {
+                        substituted.push(rule.clone());
+                        continue;
+                    }
+                    for inner in rules.iter().filter(|r| r.symbol == b) {
+                        let mut new = Rule::new(a, rule.span);
+                        if let Some(sem) = semantic_at(rule, 0) {
+                            place(self, &mut new, sem, &mut warnings);
+                        }
+                        append(self, &mut new, inner, 0, &mut warnings);
+                        if let Some(sem) = reduce_semantic(inner) {
+                            place(self, &mut new, sem, &mut warnings);
+                        }
+                        append(self, &mut new, rule, 1, &mut warnings);
+                        new.reduce_sem = rule.reduce_sem;
+                        new.reduce_sem_span = rule.reduce_sem_span;
+                        new.precedence = rule.precedence.or(inner.precedence);
+                        substituted.push(new);
+                    }
+                }
+                replace(&mut rules, a, substituted);
+            }
+
+            let recursive = |r: &Rule| r.tokens.first() == Some(&GrammarSymbol::Symbol(a));
+            for cycle in rules
+                .iter()
+                .filter(|r| r.symbol == a && r.tokens.len() == 1)
+            {
+                if recursive(cycle) {
+                    let symbol = self.get_symbol(a);
+                    warnings.push(
+                        Diagnostic::warning(format!("dropping the cycle {symbol} -> {symbol}"))
+                            .at(self.locate(cycle.span))
+                            .note("it only makes the grammar ambiguous"),
+                    );
+                }
+            }
+            rules.retain(|r| !(r.symbol == a && r.tokens.len() == 1 && recursive(r)));
+            let of_a = rules.iter().filter(|r| r.symbol == a);
+            // Without a rule to start from, `A` derives nothing, which
+            // the analysis already reports
+            if !of_a.clone().any(recursive) || of_a.clone().all(recursive) {
+                continue;
+            }
+
+            let helper = self.add_symbol_suffixed(a, "tail");
+            let mut starts = Vec::new();
+            let mut tails = Vec::new();
+            for rule in rules.iter().filter(|r| r.symbol == a) {
+                let mut new;
+                if recursive(rule) {
+                    new = Rule::new(helper, rule.span);
+                    if let Some((sem, span)) = semantic_at(rule, 0) {
+                        warnings.push(dropped(self, sem, span.unwrap_or(rule.span)).note(
+                            format!(
+                                "it runs before the left recursive {}, which the rewritten rule doesn't start with",
+                                self.get_symbol(a)
+                            ),
+                        ));
+                    }
+                    append(self, &mut new, rule, 1, &mut warnings);
+                } else {
+                    new = Rule::new(a, rule.span);
+                    append(self, &mut new, rule, 0, &mut warnings);
+                }
+                if let Some(sem) = reduce_semantic(rule) {
+                    place(self, &mut new, sem, &mut warnings);
+                }
+                push(&mut new, GrammarSymbol::Symbol(helper), rule.span);
+                new.precedence = rule.precedence;
+                if recursive(rule) {
+                    tails.push(new);
+                } else {
+                    starts.push(new);
+                }
+            }
+            tails.push(Rule::new(helper, tails[0].span));
+            starts.append(&mut tails);
+            replace(&mut rules, a, starts);
+        }
+        self.set_written_rules(rules);
+
+        let analysis = Analysis::new(self);
+        for symbol in symbols_in_order(self.written_rules()) {
+            if !analysis.is_useless(symbol)
+                && left_corners(self.written_rules(), symbol, Some(self)).contains(&symbol)
+            {
+                let rule = self
+                    .get_rules()
+                    .iter()
+                    .find(|r| r.symbol == symbol)
+                    .unwrap();
+                warnings.push(
+                    Diagnostic::warning(format!(
+                        "{} is still left recursive",
+                        self.get_symbol(symbol)
+                    ))
+                    .at(self.locate(rule.span))
+                    .note("through symbols that derive the empty string, which aren't rewritten"),
+                );
+            }
+        }
+        warnings
+    }
+
+    /// Left-factors the alternatives of each symbol: `A -> α β | α γ`
+    /// becomes `A -> α A_rest` and `A_rest -> β | γ`, `α` being the
+    /// longest prefix shared by the alternatives starting like the first one
+    /// that starts like another. Helpers are factored in turn. The
+    /// semantics in `α` are those of the first alternative, the others are
+    /// dropped where they differ.
+    pub fn left_factor(&mut self) -> Vec<Diagnostic> {
+        let mut warnings = Vec::new();
+        let mut rules = self.written_rules().to_vec();
+        let mut queue = symbols_in_order(&rules);
+        let mut next = 0;
+        while let Some(&a) = queue.get(next) {
+            let alternatives = (0..rules.len())
+                .filter(|&r| rules[r].symbol == a)
+                .collect::<Vec<_>>();
+            let group = alternatives.iter().find_map(|&r| {
+                let first = rules[r].tokens.first()?;
+                let group = alternatives
+                    .iter()
+                    .copied()
+                    .filter(|&o| rules[o].tokens.first() == Some(first))
+                    .collect::<Vec<_>>();
+                (group.len() > 1).then_some(group)
+            });
+            let Some(group) = group else {
+                next += 1;
+                continue;
+            };
+            let first = &rules[group[0]];
+            let len = (0..first.tokens.len())
+                .take_while(|&i| {
+                    group
+                        .iter()
+                        .all(|&r| rules[r].tokens.get(i) == Some(&first.tokens[i]))
+                })
+                .count();
+
+            let helper = self.add_symbol_suffixed(a, "rest");
+            let mut factored = Rule::new(a, first.span);
+            for i in 0..len {
+                let kept = semantic_at(first, i);
+                if let Some(sem) = kept {
+                    place(self, &mut factored, sem, &mut warnings);
+                }
+                for &other in &group[1..] {
+                    let other = &rules[other];
+                    let Some((sem, span)) = semantic_at(other, i) else {
+                        continue;
+                    };
+                    if kept.map(|(k, _)| k) != Some(sem) {
+                        warnings.push(dropped(self, sem, span.unwrap_or(other.span)).note(
+                            format!(
+                                "the prefix factored out of {} keeps the semantics of its first alternative",
+                                self.get_symbol(a)
+                            ),
+                        ));
+                    }
+                }
+                push(&mut factored, first.tokens[i], first.token_spans[i]);
+            }
+            push(&mut factored, GrammarSymbol::Symbol(helper), first.span);
+            factored.precedence = first.tokens[..len].iter().rev().find_map(|s| match *s {
+                GrammarSymbol::Token(t) => self.get_precedence(t),
+                GrammarSymbol::Symbol(_) => None,
+            });
+
+            let mut of_a = Vec::new();
+            for &r in &alternatives {
+                if r == group[0] {
+                    of_a.push(factored.clone());
+                } else if !group.contains(&r) {
+                    of_a.push(rules[r].clone());
+                }
+            }
+            for &r in &group {
+                let rule = &rules[r];
+                let mut tail = Rule::new(helper, rule.span);
+                append(self, &mut tail, rule, len, &mut warnings);
+                tail.reduce_sem = rule.reduce_sem;
+                tail.reduce_sem_span = rule.reduce_sem_span;
+                tail.precedence = rule.precedence;
+                of_a.push(tail);
+            }
+            replace(&mut rules, a, of_a);
+            queue.push(helper);
+        }
+        self.set_written_rules(rules);
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    fn grammar(lines: &[&str]) -> Grammar {
+        Grammar::new(lines.iter().map(|l| Cow::Borrowed(*l))).unwrap()
+    }
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn direct_left_recursion() {
+        let mut g = grammar(&[
+            "%left '+'",
+            "%left '*'",
+            "E -> E '+' {plus} T R{add} | T R{pass}",
+            "T -> T '*' F R{mul} | F",
+            "F -> '(' E ')' | id",
+        ]);
+        assert!(g.remove_left_recursion().is_empty());
+        assert_eq!(
+            g.to_source(),
+            "%token + * ( ) id
+%left +
+%left *
+%start E
+
+E -> T {pass} E_tail
+E_tail -> + {plus} T {add} E_tail
+E_tail ->
+T -> F T_tail
+T_tail -> * F {mul} T_tail
+T_tail ->
+F -> ( E )
+F -> id
+"
+        );
+    }
+
+    #[test]
+    fn indirect_left_recursion_and_factoring() {
+        let mut g = grammar(&[
+            "S -> A a | b",
+            "A -> A c | S d | {s0} S e | A",
+            "X -> if c then s | if c then s else s R{ifelse} | {pre} if c do",
+        ]);
+        let warnings = messages(&g.remove_left_recursion());
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("dropping the cycle A -> A"));
+        assert!(warnings[1].contains("dropping semantic s0"));
+        let warnings = messages(&g.left_factor());
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("dropping semantic s0"));
+        assert!(warnings[1].contains("dropping semantic pre"));
+        assert_eq!(
+            g.to_source(),
+            "%token a b c d e if then s else do
+%start S
+
+S -> A a
+S -> b
+A -> b A_rest
+A_rest -> d A_tail
+A_rest -> e A_tail
+A_tail -> c A_tail
+A_tail -> a A_tail_rest
+A_tail ->
+A_tail_rest -> d A_tail
+A_tail_rest -> e A_tail
+X -> if c X_rest
+X_rest -> then s X_rest_rest
+X_rest -> do
+X_rest_rest ->
+X_rest_rest -> else s R{ifelse}
+"
+        );
+    }
+
+    #[test]
+    fn helpers_skip_taken_names() {
+        let mut g = grammar(&["E -> E a | b | E_tail", "E_tail -> c"]);
+        assert!(g.remove_left_recursion().is_empty());
+        let symbols = g
+            .iter_symbols()
+            .map(|s| g.get_symbol(s))
+            .collect::<Vec<_>>();
+        assert!(symbols.contains(&"E_tail2"));
+        assert!(g.to_source().contains("E -> E_tail E_tail2\n"));
+    }
+}
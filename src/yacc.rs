@@ -0,0 +1,565 @@
+//! Reads grammars written for yacc and Bison, so they can be migrated.
+//!
+//! Only what describes the grammar is kept: `%token`, `%left`, `%right`,
+//! `%nonassoc`, `%precedence` and `%start` from the declarations, and the
+//! rules, where `'c'` is a terminal, `"text"` the token it's an alias of and
+//! `%empty` nothing. The prologue, the epilogue, `%union`, `%type` and the
+//! other directives about the generated C code are skipped, and directives
+//! this doesn't know are passed on so the parser warns about them.
+//!
+//! Each action becomes a semantic named after the rule's symbol and how many
+//! actions that symbol had before, `expr_0`, `expr_1` and so on. The action
+//! ending an alternative runs on reduce, as in yacc, one in the middle of it
+//! where it's written. The C code of every action is kept, to be rewritten
+//! in `semantics.replacements`.
+
+use std::collections::HashMap;
+
+use crate::{
+    diagnostic::{Diagnostic, SourceFile, Span},
+    error::{Error, Result},
+    lexer::{Lexeme, LexemeKind},
+};
+
+/// Directives with nothing to do with the grammar itself
+const SKIPPED: [&str; 21] = [
+    "code",
+    "debug",
+    "define",
+    "defines",
+    "destructor",
+    "error-verbose",
+    "expect",
+    "expect-rr",
+    "file-prefix",
+    "initial-action",
+    "language",
+    "lex-param",
+    "locations",
+    "name-prefix",
+    "output",
+    "param",
+    "parse-param",
+    "printer",
+    "pure-parser",
+    "type",
+    "union",
+];
+
+/// The statements read from a yacc file, in the form the parser reads,
+/// and the C code of its actions by semantic name
+pub(crate) struct Import {
+    pub statements: Vec<Vec<Lexeme>>,
+    pub actions: Vec<(String, String)>,
+}
+
+struct Reader<'a> {
+    source: &'a SourceFile,
+    file: usize,
+    line: usize,
+    /// Byte offset in the current line
+    pos: usize,
+    /// The token named by each `"text"` alias
+    aliases: HashMap<String, String>,
+    actions: Vec<(String, String)>,
+    /// How many actions each symbol has had
+    counts: HashMap<String, usize>,
+}
+
+impl<'a> Reader<'a> {
+    fn eof(&self) -> bool {
+        self.line >= self.source.lines.len()
+    }
+
+    fn rest(&self) -> &'a str {
+        self.source
+            .lines
+            .get(self.line)
+            .map_or("", |l| &l[self.pos..])
+    }
+
+    fn next_line(&mut self) {
+        self.line += 1;
+        self.pos = 0;
+    }
+
+    fn span(&self, len: usize) -> Span {
+        self.source.span(self.file, self.line, self.pos, len)
+    }
+
+    fn error(&self, message: impl Into<String>, span: Span) -> Error {
+        Diagnostic::error(message)
+            .at(self.source.location(span))
+            .into()
+    }
+
+    /// Skips whitespace, line breaks and comments
+    fn skip_blank(&mut self) -> Result<()> {
+        while !self.eof() {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.is_empty() || trimmed.starts_with("//") {
+                self.next_line();
+            } else if trimmed.starts_with("/*") {
+                let start = self.span(2);
+                self.pos += 2;
+                self.skip_past("*/")
+                    .ok_or_else(|| self.error("unterminated comment", start))?;
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves past the next `end`, which may be on a later line
+    fn skip_past(&mut self, end: &str) -> Option<()> {
+        while !self.eof() {
+            if let Some(i) = self.rest().find(end) {
+                self.pos += i + end.len();
+                return Some(());
+            }
+            self.next_line();
+        }
+        None
+    }
+
+    /// Reads an identifier, which may contain `.` and `-` after its first
+    /// character
+    fn ident(&mut self) -> Option<(String, Span)> {
+        let rest = self.rest();
+        if !rest.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '.') {
+            return None;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '-')))
+            .unwrap_or(rest.len());
+        let ident = rest[..len].to_string();
+        let span = self.span(len);
+        self.pos += len;
+        Some((ident, span))
+    }
+
+    /// Reads a `'c'` or `"text"` literal
+    fn literal(&mut self) -> Result<(String, Span)> {
+        let rest = self.rest();
+        let quote = rest.chars().next().expect("a literal starts here");
+        let mut value = String::new();
+        let mut chars = rest.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    let Some((_, escaped)) = chars.next() else {
+                        break;
+                    };
+                    value.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        c => c,
+                    });
+                }
+                c if c == quote => {
+                    let span = self.span(i + 1);
+                    self.pos += i + 1;
+                    return Ok((value, span));
+                }
+                c => value.push(c),
+            }
+        }
+        Err(self.error("unterminated literal", self.span(rest.len())))
+    }
+
+    /// Reads a `{ ... }` block of C code, returning what's inside the
+    /// braces with the span of its first line. Braces in strings, character
+    /// literals and comments don't count
+    fn braces(&mut self) -> Result<(String, Span)> {
+        let start = (self.line, self.pos);
+        let open = self.span(1);
+        let mut code = String::new();
+        let mut depth = 0usize;
+        let mut in_comment = false;
+        while !self.eof() {
+            let line = &self.source.lines[self.line];
+            let mut chars = line[self.pos..].char_indices().peekable();
+            while let Some((i, c)) = chars.next() {
+                let at = self.pos + i;
+                if in_comment {
+                    if c == '*' && chars.next_if(|&(_, d)| d == '/').is_some() {
+                        in_comment = false;
+                    }
+                    continue;
+                }
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            code.push_str(&line[self.pos..at]);
+                            let span = if self.line == start.0 {
+                                self.source
+                                    .span(self.file, self.line, start.1, at + 1 - start.1)
+                            } else {
+                                open
+                            };
+                            self.pos = at + 1;
+                            let code = code.trim();
+                            let code = code.strip_prefix('{').unwrap_or(code);
+                            return Ok((code.trim().to_string(), span));
+                        }
+                    }
+                    '\'' | '"' => {
+                        let mut escaped = false;
+                        for (_, d) in chars.by_ref() {
+                            if d == c && !escaped {
+                                break;
+                            }
+                            escaped = d == '\\' && !escaped;
+                        }
+                    }
+                    '/' if chars.peek().is_some_and(|&(_, d)| d == '/') => break,
+                    '/' if chars.next_if(|&(_, d)| d == '*').is_some() => in_comment = true,
+                    _ => {}
+                }
+            }
+            code.push_str(&line[self.pos..]);
+            code.push('\n');
+            self.next_line();
+        }
+        Err(self.error("unterminated action", open))
+    }
+
+    /// Reads the declarations, up to the `%%` starting the rules
+    fn declarations(&mut self, statements: &mut Vec<Vec<Lexeme>>) -> Result<()> {
+        loop {
+            self.skip_blank()?;
+            if self.eof() {
+                // At the end of the last line, if the file has one
+                let end = self.source.lines.len().checked_sub(1).map(|line| {
+                    let len = self.source.lines[line].len();
+                    self.source.span(self.file, line, len, 0)
+                });
+                return Err(Diagnostic::error("expected `%%` before the rules")
+                    .at(end.and_then(|span| self.source.location(span)))
+                    .into());
+            }
+            let rest = self.rest();
+            if rest.starts_with("%%") {
+                self.pos += 2;
+                return Ok(());
+            }
+            if rest.starts_with("%{") {
+                let start = self.span(2);
+                self.skip_past("%}")
+                    .ok_or_else(|| self.error("unterminated `%{`", start))?;
+                continue;
+            }
+            if !rest.starts_with('%') {
+                return Err(self.error("expected a declaration", self.span(1)));
+            }
+            let start = self.span(1);
+            self.pos += 1;
+            let Some((directive, span)) = self.ident() else {
+                return Err(self.error("expected a declaration", start));
+            };
+            let mut statement = vec![Lexeme {
+                kind: LexemeKind::Directive(directive.clone()),
+                span: start.to(span),
+            }];
+            let mut last = None;
+            loop {
+                self.skip_blank()?;
+                let rest = self.rest();
+                if self.eof() || rest.starts_with('%') {
+                    break;
+                }
+                if rest.starts_with('<') {
+                    self.skip_past(">");
+                } else if rest.starts_with('{') {
+                    self.braces()?;
+                } else if rest.starts_with('\'') {
+                    let (value, span) = self.literal()?;
+                    let kind = LexemeKind::Quoted(value);
+                    statement.push(Lexeme { kind, span });
+                } else if rest.starts_with('"') {
+                    let (value, _) = self.literal()?;
+                    if let Some(name) = last.take() {
+                        self.aliases.insert(value, name);
+                    }
+                } else if let Some((name, span)) = self.ident() {
+                    last = Some(name.clone());
+                    let kind = LexemeKind::Word(name);
+                    statement.push(Lexeme { kind, span });
+                } else {
+                    // Token numbers, `;` and such
+                    let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    self.pos += len;
+                }
+            }
+            if !SKIPPED.contains(&directive.as_str()) {
+                statements.push(statement);
+            }
+        }
+    }
+
+    /// Whether an identifier followed by `:` starts here, which begins the
+    /// next rule even without a `;` before it
+    fn at_rule_head(&mut self) -> Result<bool> {
+        let (line, pos) = (self.line, self.pos);
+        let head = self.ident().is_some() && {
+            self.skip_blank()?;
+            self.rest().starts_with(':')
+        };
+        (self.line, self.pos) = (line, pos);
+        Ok(head)
+    }
+
+    /// Reads a terminal or nonterminal, unless there's none here
+    fn symbol(&mut self) -> Result<Option<Lexeme>> {
+        let rest = self.rest();
+        let (kind, span) = if rest.starts_with('\'') {
+            let (value, span) = self.literal()?;
+            (LexemeKind::Quoted(value), span)
+        } else if rest.starts_with('"') {
+            let (value, span) = self.literal()?;
+            match self.aliases.get(&value) {
+                Some(name) => (LexemeKind::Word(name.clone()), span),
+                None => (LexemeKind::Quoted(value), span),
+            }
+        } else if let Some((name, span)) = self.ident() {
+            (LexemeKind::Word(name), span)
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(Lexeme { kind, span }))
+    }
+
+    /// Reads the rules, up to the `%%` starting the epilogue or the end of
+    /// the file
+    fn rules(&mut self, statements: &mut Vec<Vec<Lexeme>>) -> Result<()> {
+        loop {
+            self.skip_blank()?;
+            if self.eof() || self.rest().starts_with("%%") {
+                return Ok(());
+            }
+            let Some((symbol, head)) = self.ident() else {
+                return Err(self.error("expected a rule `name: ...`", self.span(1)));
+            };
+            self.skip_blank()?;
+            if !self.rest().starts_with(':') {
+                return Err(self.error(format!("expected `:` after {symbol}"), head));
+            }
+            let mut statement = vec![
+                Lexeme {
+                    kind: LexemeKind::Word(symbol.clone()),
+                    span: head,
+                },
+                Lexeme {
+                    kind: LexemeKind::Arrow,
+                    span: self.span(1),
+                },
+            ];
+            self.pos += 1;
+            // The action ending the alternative so far, if any
+            let mut action = None;
+            loop {
+                self.skip_blank()?;
+                let rest = self.rest();
+                if self.eof() || rest.starts_with("%%") || self.at_rule_head()? {
+                    break;
+                }
+                if rest.starts_with(';') {
+                    self.pos += 1;
+                    break;
+                }
+                if rest.starts_with('|') {
+                    Self::on_reduce(&mut statement, action.take());
+                    statement.push(Lexeme {
+                        kind: LexemeKind::Pipe,
+                        span: self.span(1),
+                    });
+                    self.pos += 1;
+                } else if rest.starts_with('{') {
+                    let (code, span) = self.braces()?;
+                    if code.is_empty() {
+                        continue;
+                    }
+                    let count = self.counts.entry(symbol.clone()).or_default();
+                    let name = format!("{symbol}_{count}");
+                    *count += 1;
+                    self.actions.push((name.clone(), code));
+                    action = Some(statement.len());
+                    let kind = LexemeKind::Semantic(name);
+                    statement.push(Lexeme { kind, span });
+                } else if rest.starts_with('%') {
+                    let start = self.span(1);
+                    self.pos += 1;
+                    match self.ident() {
+                        Some((directive, span)) if directive == "prec" => {
+                            statement.push(Lexeme {
+                                kind: LexemeKind::Directive(directive),
+                                span: start.to(span),
+                            });
+                            self.skip_blank()?;
+                            let Some(token) = self.symbol()? else {
+                                return Err(self.error("expected a token after %prec", span));
+                            };
+                            statement.push(token);
+                        }
+                        Some((directive, _)) if directive == "empty" => {}
+                        Some((directive, span)) => {
+                            return Err(self.error(
+                                format!("unsupported %{directive} in a rule"),
+                                start.to(span),
+                            ));
+                        }
+                        None => return Err(self.error("expected a directive", start)),
+                    }
+                } else if let Some(lexeme) = self.symbol()? {
+                    action = None;
+                    statement.push(lexeme);
+                } else {
+                    return Err(self.error("unexpected character in a rule", self.span(1)));
+                }
+            }
+            Self::on_reduce(&mut statement, action);
+            statements.push(statement);
+        }
+    }
+
+    /// Makes the action ending an alternative run on reduce
+    fn on_reduce(statement: &mut [Lexeme], action: Option<usize>) {
+        if let Some(i) = action {
+            if let LexemeKind::Semantic(name) = &statement[i].kind {
+                statement[i].kind = LexemeKind::ReduceSemantic(name.clone());
+            }
+        }
+    }
+}
+
+/// Reads the declarations and rules of a yacc file
+pub(crate) fn import(source: &SourceFile, file: usize) -> Result<Import> {
+    let mut reader = Reader {
+        source,
+        file,
+        line: 0,
+        pos: 0,
+        aliases: HashMap::new(),
+        actions: Vec::new(),
+        counts: HashMap::new(),
+    };
+    let mut statements = Vec::new();
+    reader.declarations(&mut statements)?;
+    reader.rules(&mut statements)?;
+    Ok(Import {
+        statements,
+        actions: reader.actions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::{Associativity, Grammar};
+
+    const CALC: &str = r#"%{
+#include <stdio.h>
+%}
+%union { double val; }
+%token <val> NUM "number"
+%token PLUS "+"
+%left PLUS '-'
+%left '*'
+%right UMINUS
+%start input
+%%
+input: %empty | input line ;
+line: '\n' | exp '\n' { printf("%g\n", $1); } ;
+exp:      NUM                { $$ = $1; }
+        | exp "+" exp        { $$ = $1 + $3; }
+        | exp '-' exp        { $$ = $1 - $3; }
+        | exp '*' exp        { if ($3) { yyerror("}"); } }
+        | '-' exp %prec UMINUS { $$ = -$2; }
+        | '(' exp ')'        { $$ = $2; }
+        ;
+%%
+int main() { return yyparse(); }
+"#;
+
+    fn source(text: &str) -> SourceFile {
+        SourceFile {
+            name: "test.y".to_string(),
+            lines: text.lines().map(str::to_string).collect(),
+        }
+    }
+
+    /// Imports `text` the way a `.y` file given in the config is
+    fn grammar(text: &str, name: &str) -> Grammar {
+        let path =
+            std::env::temp_dir().join(format!("grammar-gen-{}-{name}.y", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let grammar = Grammar::from_files(&[&path]);
+        std::fs::remove_file(&path).unwrap();
+        grammar.unwrap()
+    }
+
+    #[test]
+    fn empty_file_is_an_error() {
+        for text in ["", "  \n\t\n"] {
+            let Err(Error::Grammar(diagnostic)) = import(&source(text), 0) else {
+                panic!("{text:?} imported");
+            };
+            assert!(diagnostic
+                .to_string()
+                .contains("expected `%%` before the rules"));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let imported = grammar(CALC, "calc");
+        assert!(imported.diagnostics().is_empty());
+        let exported = imported.to_bison();
+        let again = grammar(&exported, "again");
+        assert_eq!(again.to_bison(), exported);
+        assert_eq!(again.get_rules().len(), imported.get_rules().len());
+        let actions = imported
+            .iter_semantics()
+            .map(|s| (imported.get_semantic(s), imported.get_action(s).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(actions[0], ("line_0", r#"printf("%g\n", $1);"#));
+        assert_eq!(actions[4], ("exp_3", r#"if ($3) { yyerror("}"); }"#));
+        assert_eq!(actions.len(), 7);
+    }
+
+    #[test]
+    fn braces_in_comments() {
+        let text = "%%\nS: a { /* } */ x; /* {\n */ } | b { y; } ;";
+        let import = import(&source(text), 0).unwrap();
+        assert_eq!(
+            import.actions,
+            [
+                ("S_0".to_string(), "/* } */ x; /* {\n */".to_string()),
+                ("S_1".to_string(), "y;".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn precedence_without_associativity() {
+        let g = grammar(
+            "%left '+'\n%precedence NEG\n%%\ne: e '+' e | '-' e %prec NEG | NUM ;",
+            "precedence",
+        );
+        let precedence = |name| {
+            let token = g.iter_tokens().find(|&t| g.get_token(t) == name).unwrap();
+            g.get_precedence(token).unwrap()
+        };
+        assert_eq!(precedence("+").associativity, Associativity::Left);
+        assert_eq!(precedence("NEG").associativity, Associativity::Precedence);
+        assert!(precedence("NEG").level > precedence("+").level);
+        assert!(g.to_bison().contains("%precedence NEG\n"));
+    }
+}